@@ -0,0 +1,107 @@
+use super::Buffer;
+use crate::Error;
+use std::convert::TryFrom;
+
+#[derive(Clone, Copy)]
+pub enum BlendMode {
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Difference,
+    Add,
+    Subtract,
+}
+
+impl BlendMode {
+    // every channel is in [0, 1]
+    fn blend(self, base: f32, layer: f32) -> f32 {
+        match self {
+            Self::Normal => layer,
+            Self::Multiply => base * layer,
+            Self::Screen => 1. - (1. - base) * (1. - layer),
+            Self::Overlay => if base < 0.5 {
+                2. * base * layer
+            } else {
+                1. - 2. * (1. - base) * (1. - layer)
+            },
+            Self::Difference => (base - layer).abs(),
+            Self::Add => (base + layer).min(1.),
+            Self::Subtract => (base - layer).max(0.),
+        }
+    }
+}
+
+impl TryFrom<&str> for BlendMode {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "normal" => Ok(Self::Normal),
+            "multiply" => Ok(Self::Multiply),
+            "screen" => Ok(Self::Screen),
+            "overlay" => Ok(Self::Overlay),
+            "difference" => Ok(Self::Difference),
+            "add" => Ok(Self::Add),
+            "subtract" => Ok(Self::Subtract),
+            unknown => Err(Error::invalid_parameter("mode", format!("\"{}\" isn't a valid blend mode.", unknown))),
+        }
+    }
+}
+
+impl std::fmt::Display for BlendMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mode_name = match self {
+            BlendMode::Normal => "normal",
+            BlendMode::Multiply => "multiply",
+            BlendMode::Screen => "screen",
+            BlendMode::Overlay => "overlay",
+            BlendMode::Difference => "difference",
+            BlendMode::Add => "add",
+            BlendMode::Subtract => "subtract",
+        };
+
+        f.write_str(mode_name)
+    }
+}
+
+// blend `layer` over `base`, following the W3C compositing and blending spec:
+// the blended color is mixed with the layer color by the base alpha,
+// then composited over the base with the source-over operator.
+pub fn blend(base: &Buffer, layer: &Buffer, mode: BlendMode, opacity: f32) -> Result<Buffer, Error> {
+    let dim = base.dimensions();
+    if dim != layer.dimensions() {
+        return Err(Error::DimensionMismatch { expected: dim, found: layer.dimensions() });
+    }
+    if !(0. ..=1.).contains(&opacity) {
+        return Err(Error::invalid_parameter("opacity", "must be between 0 and 1"));
+    }
+
+    let mut buffer = Buffer::new(dim.0, dim.1);
+    buffer.pixels_mut()
+        .zip(
+            base.pixels()
+                .zip(layer.pixels())
+        )
+        .for_each(|(buffer, (base, layer))| {
+            let base_alpha = base.0[3] as f32 / 255.;
+            let layer_alpha = layer.0[3] as f32 / 255. * opacity;
+            let alpha = layer_alpha + base_alpha * (1. - layer_alpha);
+
+            for channel in 0..3 {
+                let base_color = base.0[channel] as f32 / 255.;
+                let layer_color = layer.0[channel] as f32 / 255.;
+                let mixed = (1. - base_alpha) * layer_color + base_alpha * mode.blend(base_color, layer_color);
+                let color = if alpha > 0. {
+                    (layer_alpha * mixed + base_alpha * (1. - layer_alpha) * base_color) / alpha
+                } else {
+                    0.
+                };
+
+                buffer.0[channel] = (color * 255.).round() as u8;
+            }
+            buffer.0[3] = (alpha * 255.).round() as u8;
+        });
+
+    Ok(buffer)
+}
@@ -1,7 +1,11 @@
 use super::Buffer;
-use crate::pixel;
+use crate::{pixel, Error, ProcessContext};
 
 pub fn blur(img: &Buffer, radius: u32) -> Buffer {
+    blur_with(img, radius, &ProcessContext::default()).expect("the default context is never cancelled")
+}
+
+pub fn blur_with(img: &Buffer, radius: u32, ctx: &ProcessContext) -> Result<Buffer, Error> {
     let (width, height) = img.dimensions();
     let mut sum_table = vec![[0; 4]; (width * height) as usize];
 
@@ -37,6 +41,7 @@ pub fn blur(img: &Buffer, radius: u32) -> Buffer {
     let mut buffer = Buffer::new(width, height);
 
     for y in 0..height {
+        ctx.check()?;
         let y_max = y.saturating_add(radius).min(height - 1);
         let (y_min, overflow_y) = match y.overflowing_sub(radius + 1) {
             (_, true) => (u32::MIN, true),
@@ -55,18 +60,21 @@ pub fn blur(img: &Buffer, radius: u32) -> Buffer {
             let pix_min = if overflow_x || overflow_y { [0; 4] } else { sum_table[(x_min + y_min * width) as usize] };
             let pix_min_col = if overflow_y { [0; 4] } else { sum_table[(x_mas + y_min * width) as usize]};
             let pix_min_row = if overflow_x { [0; 4] } else { sum_table[(x_min + y_max * width) as usize]};
+            let sum = pixel::sub(pixel::add(pix_max, pix_min), pixel::add(pix_min_col, pix_min_row));
             let neighbours = (x_mas - x_min + overflow_x as u32) * y_len;
 
             let avg = [
-                ((pix_max[0] + pix_min[0] - pix_min_col[0] - pix_min_row[0]) / neighbours) as u8,
-                ((pix_max[1] + pix_min[1] - pix_min_col[1] - pix_min_row[1]) / neighbours) as u8,
-                ((pix_max[2] + pix_min[2] - pix_min_col[2] - pix_min_row[2]) / neighbours) as u8,
-                ((pix_max[3] + pix_min[3] - pix_min_col[3] - pix_min_row[3]) / neighbours) as u8,
+                (sum[0] / neighbours) as u8,
+                (sum[1] / neighbours) as u8,
+                (sum[2] / neighbours) as u8,
+                (sum[3] / neighbours) as u8,
             ];
 
             buffer.put_pixel(x, y, image::Rgba(avg))
         }
+
+        ctx.row_done(y + 1, height);
     }
 
-    buffer
+    Ok(buffer)
 }
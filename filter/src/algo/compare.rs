@@ -1,17 +1,13 @@
 use super::Buffer;
-
-#[derive(Debug)]
-pub enum CompareError {
-    MismatchSize,
-}
+use crate::Error;
 
 const SAME: image::Rgba<u8> = image::Rgba([0, 0, 0, 255]);
 const DIFF: image::Rgba<u8> = image::Rgba([190, 0, 0, 255]);
 
-pub fn compare(lhs: &Buffer, rhs: &Buffer) -> Result<Buffer, CompareError> {
+pub fn compare(lhs: &Buffer, rhs: &Buffer) -> Result<Buffer, Error> {
     let dim = lhs.dimensions();
     if dim != rhs.dimensions() {
-        return Err(CompareError::MismatchSize);
+        return Err(Error::DimensionMismatch { expected: dim, found: rhs.dimensions() });
     }
 
     let mut buffer = Buffer::new(dim.0, dim.1);
@@ -26,11 +22,3 @@ pub fn compare(lhs: &Buffer, rhs: &Buffer) -> Result<Buffer, CompareError> {
 
     Ok(buffer)
 }
-
-impl std::fmt::Display for CompareError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "The two images doesn't have the same size.")
-    }
-}
-
-impl std::error::Error for CompareError {}
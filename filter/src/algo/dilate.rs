@@ -1,8 +1,12 @@
 use super::{Buffer, compute_buffer};
-use crate::pixel;
+use crate::{pixel, Error, ProcessContext};
 
 pub fn dilate(img: &Buffer, radius: u32) -> Buffer {
+    dilate_with(img, radius, &ProcessContext::default()).expect("the default context is never cancelled")
+}
+
+pub fn dilate_with(img: &Buffer, radius: u32, ctx: &ProcessContext) -> Result<Buffer, Error> {
     compute_buffer(img, radius, [u8::MIN; 4],
-        pixel::max, pixel::max, |max, _| max
+        pixel::max, pixel::max, |max, _| max, ctx
     )
 }
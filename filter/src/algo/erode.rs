@@ -1,8 +1,12 @@
 use super::{Buffer, compute_buffer};
-use crate::pixel;
+use crate::{pixel, Error, ProcessContext};
 
 pub fn erode(img: &Buffer, radius: u32) -> Buffer {
+    erode_with(img, radius, &ProcessContext::default()).expect("the default context is never cancelled")
+}
+
+pub fn erode_with(img: &Buffer, radius: u32, ctx: &ProcessContext) -> Result<Buffer, Error> {
     compute_buffer(img, radius, [u8::MAX; 4],
-        pixel::min, pixel::min, |min, _| min
+        pixel::min, pixel::min, |min, _| min, ctx
     )
 }
@@ -1,7 +1,11 @@
 use super::Buffer;
-use crate::pixel;
+use crate::{pixel, Error, ProcessContext};
 
 pub fn local_contrast(img: &Buffer, radius: u32, factor: i32) -> Buffer {
+    local_contrast_with(img, radius, factor, &ProcessContext::default()).expect("the default context is never cancelled")
+}
+
+pub fn local_contrast_with(img: &Buffer, radius: u32, factor: i32, ctx: &ProcessContext) -> Result<Buffer, Error> {
     let (width, height) = img.dimensions();
     let mut sum_table = vec![[0; 4]; (width * height) as usize];
 
@@ -37,6 +41,7 @@ pub fn local_contrast(img: &Buffer, radius: u32, factor: i32) -> Buffer {
     let mut buffer = Buffer::new(width, height);
 
     for y in 0..height {
+        ctx.check()?;
         let y_max = y.saturating_add(radius).min(height - 1);
         let (y_min, overflow_y) = match y.overflowing_sub(radius + 1) {
             (_, true) => (u32::MIN, true),
@@ -55,14 +60,15 @@ pub fn local_contrast(img: &Buffer, radius: u32, factor: i32) -> Buffer {
             let pix_min = if overflow_x || overflow_y { [0; 4] } else { sum_table[(x_min + y_min * width) as usize] };
             let pix_min_col = if overflow_y { [0; 4] } else { sum_table[(x_max + y_min * width) as usize]};
             let pix_min_row = if overflow_x { [0; 4] } else { sum_table[(x_min + y_max * width) as usize]};
+            let sum = pixel::sub(pixel::add(pix_max, pix_min), pixel::add(pix_min_col, pix_min_row));
             let neighbours = (x_max - x_min + overflow_x as u32) * y_len;
 
             let pix = img.get_pixel(x, y).0;
             let avg = [
-                ((pix_max[0] + pix_min[0] - pix_min_col[0] - pix_min_row[0]) / neighbours) as i32,
-                ((pix_max[1] + pix_min[1] - pix_min_col[1] - pix_min_row[1]) / neighbours) as i32,
-                ((pix_max[2] + pix_min[2] - pix_min_col[2] - pix_min_row[2]) / neighbours) as i32,
-                ((pix_max[3] + pix_min[3] - pix_min_col[3] - pix_min_row[3]) / neighbours) as i32,
+                (sum[0] / neighbours) as i32,
+                (sum[1] / neighbours) as i32,
+                (sum[2] / neighbours) as i32,
+                (sum[3] / neighbours) as i32,
             ];
 
             let contrast = [
@@ -79,7 +85,9 @@ pub fn local_contrast(img: &Buffer, radius: u32, factor: i32) -> Buffer {
                 (pix[3] as i32 + contrast[3]).clamp(0, 255) as u8,
             ]));
         }
+
+        ctx.row_done(y + 1, height);
     }
 
-    buffer
+    Ok(buffer)
 }
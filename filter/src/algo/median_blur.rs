@@ -1,13 +1,18 @@
 use super::Buffer;
-use crate::pixel;
+use crate::{pixel, Error, ProcessContext};
 
 pub fn median_blur(img: &Buffer, radius: u32) -> Buffer {
+    median_blur_with(img, radius, &ProcessContext::default()).expect("the default context is never cancelled")
+}
+
+pub fn median_blur_with(img: &Buffer, radius: u32, ctx: &ProcessContext) -> Result<Buffer, Error> {
     let (width, height) = img.dimensions();
     let capacity = (radius * 2 + 1).pow(2) as usize;
     let mut container = Vec::with_capacity(capacity);
     let mut buffer = Buffer::new(width, height);
 
     for y in 0..height {
+        ctx.check()?;
         let y_max = y.saturating_add(radius + 1).min(height);
         let y_min = y.saturating_sub(radius);
 
@@ -25,11 +30,13 @@ pub fn median_blur(img: &Buffer, radius: u32) -> Buffer {
                 }
             }
 
-            container.sort_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+            container.sort_by_key(|(br, _)| *br);
             let median = container[container.len() / 2].1;
             buffer.put_pixel(x, y, image::Rgba(median));
         }
+
+        ctx.row_done(y + 1, height);
     }
 
-    buffer
+    Ok(buffer)
 }
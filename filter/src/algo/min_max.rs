@@ -1,17 +1,30 @@
 use super::Buffer;
-use crate::pixel;
+use crate::{pixel, Error, ProcessContext};
 
 pub fn min_max(img: &Buffer, radius: u32) -> Buffer {
+    min_max_with(img, radius, &ProcessContext::default()).expect("the default context is never cancelled")
+}
+
+pub fn min_max_with(img: &Buffer, radius: u32, ctx: &ProcessContext) -> Result<Buffer, Error> {
     let (width, height) = img.dimensions();
     let mut buffer = Buffer::new(width, height);
 
     for y in 0..height {
+        ctx.check()?;
         let y_max = y.saturating_add(radius + 1).min(height);
         let y_min = y.saturating_sub(radius);
 
         for x in 0..width {
             let x_max = x.saturating_add(radius + 1).min(width);
             let x_min = x.saturating_sub(radius);
+            let pix = img.get_pixel(x, y).0;
+
+            // a single pixel has nothing to be clamped between
+            if (x_max - x_min) * (y_max - y_min) == 1 {
+                buffer.put_pixel(x, y, image::Rgba(pix));
+                continue;
+            }
+
             let mut min = [u8::MAX; 4];
             let mut max = [u8::MIN; 4];
 
@@ -27,7 +40,6 @@ pub fn min_max(img: &Buffer, radius: u32) -> Buffer {
                 }
             }
 
-            let pix = img.get_pixel(x, y).0;
             let min_max = [
                 if pix[0] < min[0] { min[0] } else if pix[0] < max[0] { pix[0] } else { max[0] },
                 if pix[1] < min[1] { min[1] } else if pix[1] < max[1] { pix[1] } else { max[1] },
@@ -37,7 +49,9 @@ pub fn min_max(img: &Buffer, radius: u32) -> Buffer {
 
             buffer.put_pixel(x, y, image::Rgba(min_max));
         }
+
+        ctx.row_done(y + 1, height);
     }
 
-    buffer
+    Ok(buffer)
 }
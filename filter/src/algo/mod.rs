@@ -1,17 +1,27 @@
 pub mod local_contrast;
 pub mod median_blur;
 pub mod compare;
+pub mod blend;
 pub mod min_max;
 pub mod dilate;
 pub mod erode;
 pub mod blur;
+pub mod roi;
+pub mod params;
+pub mod registry;
 
 use image::{ImageBuffer, Rgba};
 use std::convert::TryFrom;
 use std::path::Path;
+use std::sync::Arc;
+use roi::Roi;
+use params::Parameter;
+use registry::AlgorithmInfo;
+use crate::{Error, ProcessContext};
 
 pub type Buffer = ImageBuffer<Rgba<u8>, Vec<u8>>;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Algorithms {
     Blur(u32),
     Dilate(u32),
@@ -51,60 +61,140 @@ impl Algorithms {
     pub fn need_factor(&self) -> bool {
         matches!(self, Self::LocalContrast(..))
     }
+
+    pub fn info(&self) -> &'static AlgorithmInfo {
+        let variant = std::mem::discriminant(self);
+        registry::registry().iter()
+            .find(|info| std::mem::discriminant(&info.default) == variant)
+            .expect("every algorithm is registered")
+    }
+
+    pub fn parameters(&self) -> &'static [Parameter] {
+        self.info().parameters
+    }
+
+    // same order as `parameters`
+    fn values(&self) -> Vec<i64> {
+        match *self {
+            Self::Blur(r) |
+            Self::Dilate(r) |
+            Self::Erode(r) |
+            Self::MedianBlur(r) |
+            Self::MinMax(r) => vec![r as i64],
+            Self::LocalContrast(r, f) => vec![r as i64, f as i64],
+        }
+    }
+
+    // every parameter with its value, ex: [("radius", 3), ("factor", 5)]
+    pub fn arguments(&self) -> Vec<(&'static str, i64)> {
+        self.parameters().iter()
+            .map(|parameter| parameter.name)
+            .zip(self.values())
+            .collect()
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        self.parameters().iter()
+            .zip(self.values())
+            .try_for_each(|(parameter, value)| parameter.validate(value))
+    }
 }
 
 impl TryFrom<&str> for Algorithms {
-    type Error = String;
+    type Error = Error;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        match value {
-            "blur" => Ok(Self::Blur(0)),
-            "dilate" => Ok(Self::Dilate(0)),
-            "erode" => Ok(Self::Erode(0)),
-            "local_contrast" => Ok(Self::LocalContrast(0, 0)),
-            "median_blur" => Ok(Self::MedianBlur(0)),
-            "min_max" => Ok(Self::MinMax(0)),
-            unknown => Err(format!("\"{}\" isn't a valid algorithm name.", unknown)),
-        }
+        registry::registry().iter()
+            .find(|info| info.name == value)
+            .map(|info| info.default)
+            .ok_or_else(|| Error::UnknownAlgorithm(String::from(value)))
     }
 }
 
 impl std::fmt::Display for Algorithms {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let algo_name = match self {
-            Algorithms::Blur(_) => "blur",
-            Algorithms::Dilate(_) => "dilate",
-            Algorithms::Erode(_) => "erode",
-            Algorithms::LocalContrast(..) => "local contrast",
-            Algorithms::MedianBlur(_) => "median blur",
-            Algorithms::MinMax(_) => "min max",
-        };
-
-        f.write_str(algo_name)
+        f.write_str(self.info().display_name)
     }
 }
 
-pub fn run_algo(source: &Path, dest: &Path, algo: Algorithms) -> Result<(), image::ImageError> {
-    let img = image::open(source)?.into_rgba8();
+pub fn process(img: &Buffer, algo: &Algorithms, roi: Option<&Roi>) -> Result<Buffer, Error> {
+    process_with(img, algo, roi, &ProcessContext::default())
+}
+
+pub fn process_with(img: &Buffer, algo: &Algorithms, roi: Option<&Roi>, ctx: &ProcessContext) -> Result<Buffer, Error> {
+    algo.validate()?;
+    if img.width() == 0 || img.height() == 0 {
+        return Err(Error::invalid_parameter("image", "the image is empty"));
+    }
 
-    let buffer = match algo {
-        Algorithms::Blur(radius) => blur::blur(&img, radius),
-        Algorithms::Dilate(radius) => dilate::dilate(&img, radius),
-        Algorithms::Erode(radius) => erode::erode(&img, radius),
-        Algorithms::LocalContrast(radius, factor) => local_contrast::local_contrast(&img, radius, factor),
-        Algorithms::MedianBlur(radius) => median_blur::median_blur(&img, radius),
-        Algorithms::MinMax(radius) => min_max::min_max(&img, radius),
+    let buffer = match *algo {
+        Algorithms::Blur(radius) => blur::blur_with(img, radius, ctx)?,
+        Algorithms::Dilate(radius) => dilate::dilate_with(img, radius, ctx)?,
+        Algorithms::Erode(radius) => erode::erode_with(img, radius, ctx)?,
+        Algorithms::LocalContrast(radius, factor) => local_contrast::local_contrast_with(img, radius, factor, ctx)?,
+        Algorithms::MedianBlur(radius) => median_blur::median_blur_with(img, radius, ctx)?,
+        Algorithms::MinMax(radius) => min_max::min_max_with(img, radius, ctx)?,
     };
 
-    buffer.save(&dest)
+    match roi {
+        Some(roi) => roi::composite(img, &buffer, roi),
+        None => Ok(buffer),
+    }
+}
+
+// every step on the result of the previous one, the `roi` applying to the whole pipeline.
+// the progress counts the rows of all the steps
+pub fn process_pipeline(img: &Buffer, steps: &[Algorithms], roi: Option<&Roi>, ctx: &ProcessContext) -> Result<Buffer, Error> {
+    if steps.is_empty() {
+        return Err(Error::invalid_parameter("steps", "a pipeline needs at least one step"));
+    }
+    // nothing runs unless every step is valid
+    steps.iter().try_for_each(Algorithms::validate)?;
+
+    let count = steps.len() as u32;
+    let mut buffer = None;
+    for (i, step) in steps.iter().enumerate() {
+        let progress = ctx.progress.clone().map(|progress| {
+            Arc::new(move |done, total| progress(i as u32 * total + done, count * total)) as Arc<dyn Fn(u32, u32) + Send + Sync>
+        });
+        let step_ctx = ProcessContext { progress, cancel: ctx.cancel.clone() };
+
+        buffer = Some(process_with(buffer.as_ref().unwrap_or(img), step, None, &step_ctx)?);
+    }
+    let buffer = buffer.expect("a pipeline has at least one step");
+
+    match roi {
+        Some(roi) => roi::composite(img, &buffer, roi),
+        None => Ok(buffer),
+    }
 }
 
-pub fn compare_images(left: &Path, right: &Path, dest: &Path) -> Result<(), Box<dyn std::error::Error>> {
-    let left = image::open(left)?.into_rgba8();
-    let right = image::open(right)?.into_rgba8();
+pub fn open(path: &Path) -> Result<Buffer, Error> {
+    let bytes = std::fs::read(path).map_err(Error::Io)?;
+    crate::decode(&bytes)
+}
 
-    compare::compare(&left, &right)?.save(dest)?;
-    Ok(())
+pub fn save(buffer: &Buffer, dest: &Path) -> Result<(), Error> {
+    buffer.save(dest).map_err(Error::from_encoding)
+}
+
+pub fn run_algo(source: &Path, dest: &Path, algo: Algorithms, roi: Option<&Roi>) -> Result<(), Error> {
+    let img = open(source)?;
+    save(&process(&img, &algo, roi)?, dest)
+}
+
+pub fn compare_images(left: &Path, right: &Path, dest: &Path) -> Result<(), Error> {
+    let left = open(left)?;
+    let right = open(right)?;
+
+    save(&compare::compare(&left, &right)?, dest)
+}
+
+pub fn blend_images(base: &Path, layer: &Path, dest: &Path, mode: blend::BlendMode, opacity: f32) -> Result<(), Error> {
+    let base = open(base)?;
+    let layer = open(layer)?;
+
+    save(&blend::blend(&base, &layer, mode, opacity)?, dest)
 }
 
 fn compute_buffer<T>(
@@ -114,19 +204,21 @@ fn compute_buffer<T>(
     reduce: fn(&[u8; 4], &mut T),
     concat: fn(&T, &mut T),
     average: fn(T, u32) -> [u8; 4],
-) -> Buffer where T: Clone {
+    ctx: &ProcessContext,
+) -> Result<Buffer, Error> where T: Clone {
     let width = img.width();
     let height = img.height();
     let mut buffer = image::ImageBuffer::new(width, height);
     let mut partial_blur = std::collections::VecDeque::with_capacity(radius as usize * 2 + 2);
 
     for y in 0..height {
+        ctx.check()?;
         let y_max = y.saturating_add(radius + 1).min(height);
         let y_min = y.saturating_sub(radius);
         partial_blur.clear();
 
         // init partial blur
-        for neighbour_x in 0..=radius {
+        for neighbour_x in 0..width.min(radius + 1) {
             let mut acc = accumulator.clone();
 
             for neighbour_y in y_min..y_max {
@@ -166,7 +258,9 @@ fn compute_buffer<T>(
                 partial_blur.pop_front();
             }
         }
+
+        ctx.row_done(y + 1, height);
     }
 
-    buffer
+    Ok(buffer)
 }
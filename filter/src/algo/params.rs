@@ -0,0 +1,41 @@
+use crate::Error;
+use serde::Serialize;
+
+pub const DEFAULT_RADIUS: u32 = 1;
+pub const DEFAULT_FACTOR: i32 = 2;
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParameterType {
+    Integer,
+}
+
+#[derive(Serialize)]
+pub struct Parameter {
+    pub name: &'static str,
+    #[serde(rename = "type")]
+    pub kind: ParameterType,
+    pub min: i64,
+    pub max: i64,
+    pub default: i64,
+}
+
+impl Parameter {
+    pub(crate) const fn radius(max: i64) -> Self {
+        Self { name: "radius", kind: ParameterType::Integer, min: 1, max, default: DEFAULT_RADIUS as i64 }
+    }
+
+    pub(crate) const fn factor(min: i64, max: i64) -> Self {
+        Self { name: "factor", kind: ParameterType::Integer, min, max, default: DEFAULT_FACTOR as i64 }
+    }
+
+    pub fn validate(&self, value: i64) -> Result<(), Error> {
+        if value < self.min || value > self.max {
+            return Err(Error::invalid_parameter(self.name, format!(
+                "{} isn't between {} and {}", value, self.min, self.max
+            )));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+use super::Algorithms;
+use super::params::{Parameter, DEFAULT_RADIUS, DEFAULT_FACTOR};
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct AlgorithmInfo {
+    pub name: &'static str,
+    pub display_name: &'static str,
+    pub description: &'static str,
+    pub parameters: &'static [Parameter],
+    // the algorithm with every parameter set to its default
+    #[serde(skip)]
+    pub default: Algorithms,
+}
+
+// the ranges follow the cost of each algorithm:
+// summed area tables (blur, local contrast) don't depend on the radius,
+// sliding windows (dilate, erode) are linear and the others are quadratic.
+const ALGORITHMS: &[AlgorithmInfo] = &[
+    AlgorithmInfo {
+        name: "blur",
+        display_name: "blur",
+        description: "Replace each pixel by the average of its neighbourhood.",
+        parameters: &[Parameter::radius(1024)],
+        default: Algorithms::Blur(DEFAULT_RADIUS),
+    },
+    AlgorithmInfo {
+        name: "dilate",
+        display_name: "dilate",
+        description: "Replace each pixel by the maximum of its neighbourhood, bright areas grow.",
+        parameters: &[Parameter::radius(256)],
+        default: Algorithms::Dilate(DEFAULT_RADIUS),
+    },
+    AlgorithmInfo {
+        name: "erode",
+        display_name: "erode",
+        description: "Replace each pixel by the minimum of its neighbourhood, dark areas grow.",
+        parameters: &[Parameter::radius(256)],
+        default: Algorithms::Erode(DEFAULT_RADIUS),
+    },
+    AlgorithmInfo {
+        name: "local_contrast",
+        display_name: "local contrast",
+        description: "Multiply the difference between each pixel and the average of its neighbourhood by `factor`.",
+        parameters: &[Parameter::radius(1024), Parameter::factor(-255, 255)],
+        default: Algorithms::LocalContrast(DEFAULT_RADIUS, DEFAULT_FACTOR),
+    },
+    AlgorithmInfo {
+        name: "median_blur",
+        display_name: "median blur",
+        description: "Replace each pixel by the median, by brightness, of its neighbourhood. Removes salt and pepper noise.",
+        parameters: &[Parameter::radius(32)],
+        default: Algorithms::MedianBlur(DEFAULT_RADIUS),
+    },
+    AlgorithmInfo {
+        name: "min_max",
+        display_name: "min max",
+        description: "Clamp each pixel between the minimum and the maximum of its neighbours. Removes isolated pixels.",
+        parameters: &[Parameter::radius(64)],
+        default: Algorithms::MinMax(DEFAULT_RADIUS),
+    },
+];
+
+pub fn registry() -> &'static [AlgorithmInfo] {
+    ALGORITHMS
+}
@@ -0,0 +1,67 @@
+use super::Buffer;
+use crate::{Error, Limits};
+use image::GrayImage;
+use std::path::Path;
+
+pub enum Roi {
+    Rect { x: u32, y: u32, width: u32, height: u32 },
+    // grayscale weights, 0 keep the original pixel, 255 take the processed one
+    Mask(GrayImage),
+}
+
+impl Roi {
+    pub fn from_mask(path: &Path) -> Result<Self, Error> {
+        let bytes = std::fs::read(path).map_err(Error::Io)?;
+        Self::from_mask_bytes(&bytes, &Limits::default())
+    }
+
+    pub fn from_mask_bytes(bytes: &[u8], limits: &Limits) -> Result<Self, Error> {
+        let mask = crate::codec::load(bytes, limits)?;
+        Ok(Self::Mask(mask.into_luma8()))
+    }
+}
+
+pub fn composite(original: &Buffer, processed: &Buffer, roi: &Roi) -> Result<Buffer, Error> {
+    let (width, height) = original.dimensions();
+    if processed.dimensions() != (width, height) {
+        return Err(Error::DimensionMismatch { expected: (width, height), found: processed.dimensions() });
+    }
+
+    let mut buffer = original.clone();
+
+    match roi {
+        Roi::Rect { x, y, width: rect_width, height: rect_height } => {
+            let x_max = x.checked_add(*rect_width).filter(|x_max| *x_max <= width);
+            let y_max = y.checked_add(*rect_height).filter(|y_max| *y_max <= height);
+            let (x_max, y_max) = match (x_max, y_max) {
+                (Some(x_max), Some(y_max)) => (x_max, y_max),
+                _ => return Err(Error::invalid_parameter("roi", format!(
+                    "the rectangle goes beyond the {}x{} image", width, height
+                ))),
+            };
+
+            for pix_y in *y..y_max {
+                for pix_x in *x..x_max {
+                    buffer.put_pixel(pix_x, pix_y, *processed.get_pixel(pix_x, pix_y));
+                }
+            }
+        },
+        Roi::Mask(mask) => {
+            if mask.dimensions() != (width, height) {
+                return Err(Error::DimensionMismatch { expected: (width, height), found: mask.dimensions() });
+            }
+
+            buffer.pixels_mut()
+                .zip(processed.pixels().zip(mask.pixels()))
+                .for_each(|(buffer, (processed, weight))| {
+                    let weight = weight.0[0] as u32;
+                    for (orig, proc) in buffer.0.iter_mut().zip(processed.0) {
+                        // round to nearest instead of truncating
+                        *orig = ((*orig as u32 * (255 - weight) + proc as u32 * weight + 127) / 255) as u8;
+                    }
+                });
+        },
+    }
+
+    Ok(buffer)
+}
@@ -0,0 +1,260 @@
+use crate::{Buffer, Error};
+use image::{DynamicImage, ExtendedColorType, ImageEncoder, ImageFormat, ImageReader};
+use image::codecs::{bmp::BmpEncoder, jpeg::JpegEncoder, tiff::TiffEncoder, webp::WebPEncoder};
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use std::convert::TryFrom;
+use std::io::Cursor;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Format {
+    Png,
+    Jpeg,
+    WebP,
+    Bmp,
+    Tiff,
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpg",
+            Self::WebP => "webp",
+            Self::Bmp => "bmp",
+            Self::Tiff => "tiff",
+        }
+    }
+
+    pub fn mime(self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+            Self::WebP => "image/webp",
+            Self::Bmp => "image/bmp",
+            Self::Tiff => "image/tiff",
+        }
+    }
+
+    pub fn from_mime(mime: &str) -> Option<Self> {
+        match mime {
+            "image/png" => Some(Self::Png),
+            "image/jpeg" => Some(Self::Jpeg),
+            "image/webp" => Some(Self::WebP),
+            "image/bmp" => Some(Self::Bmp),
+            "image/tiff" => Some(Self::Tiff),
+            _ => None,
+        }
+    }
+
+    // the format of an encoded image, if it's one we can write back
+    pub fn guess(bytes: &[u8]) -> Option<Self> {
+        let format = image::guess_format(bytes).ok()?;
+        Self::from_image_format(format)
+    }
+
+    fn from_image_format(format: ImageFormat) -> Option<Self> {
+        match format {
+            ImageFormat::Png => Some(Self::Png),
+            ImageFormat::Jpeg => Some(Self::Jpeg),
+            ImageFormat::WebP => Some(Self::WebP),
+            ImageFormat::Bmp => Some(Self::Bmp),
+            ImageFormat::Tiff => Some(Self::Tiff),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<&str> for Format {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "png" => Ok(Self::Png),
+            "jpeg" | "jpg" => Ok(Self::Jpeg),
+            "webp" => Ok(Self::WebP),
+            "bmp" => Ok(Self::Bmp),
+            "tiff" | "tif" => Ok(Self::Tiff),
+            unknown => Err(Error::invalid_parameter("format", format!("\"{}\" isn't a valid image format.", unknown))),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Compression {
+    Fast,
+    Default,
+    Best,
+}
+
+impl TryFrom<&str> for Compression {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "fast" => Ok(Self::Fast),
+            "default" => Ok(Self::Default),
+            "best" => Ok(Self::Best),
+            unknown => Err(Error::invalid_parameter("compression", format!("\"{}\" isn't a valid compression level.", unknown))),
+        }
+    }
+}
+
+impl std::fmt::Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Compression::Fast => "fast",
+            Compression::Default => "default",
+            Compression::Best => "best",
+        };
+
+        f.write_str(name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EncodeOptions {
+    // jpeg only, from 1 to 100
+    pub quality: u8,
+    // png only
+    pub compression: Compression,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self { quality: 75, compression: Compression::Default }
+    }
+}
+
+// checked against the header of an image, before decoding its pixels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+    pub max_width: u32,
+    pub max_height: u32,
+    pub max_pixels: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self { max_width: 16384, max_height: 16384, max_pixels: 64 * 1024 * 1024 }
+    }
+}
+
+// rgba of 32 bits floats, the largest pixels a decoder returns
+const MAX_BYTES_PER_PIXEL: u64 = 16;
+
+impl Limits {
+    pub fn check(&self, width: u32, height: u32) -> Result<(), Error> {
+        if width > self.max_width || height > self.max_height {
+            return Err(Error::TooLarge(format!(
+                "{}x{} pixels, the maximum is {}x{}", width, height, self.max_width, self.max_height
+            )));
+        }
+
+        let pixels = width as u64 * height as u64;
+        if pixels > self.max_pixels {
+            return Err(Error::TooLarge(format!("{} pixels, the maximum is {}", pixels, self.max_pixels)));
+        }
+
+        Ok(())
+    }
+
+    // the same limits for the decoders of `image`, so they also cap what they allocate
+    fn decoder_limits(&self) -> image::Limits {
+        let mut limits = image::Limits::default();
+        limits.max_image_width = Some(self.max_width);
+        limits.max_image_height = Some(self.max_height);
+        limits.max_alloc = Some(self.max_pixels.saturating_mul(MAX_BYTES_PER_PIXEL));
+        limits
+    }
+}
+
+pub struct ImageInfo {
+    pub extension: &'static str,
+    pub mime: &'static str,
+    pub width: u32,
+    pub height: u32,
+}
+
+// read the format and the dimensions from the header, without decoding the pixels
+pub fn inspect(bytes: &[u8]) -> Result<ImageInfo, Error> {
+    let format = image::guess_format(bytes).map_err(Error::from_decoding)?;
+    let (width, height) = ImageReader::with_format(Cursor::new(bytes), format)
+        .into_dimensions()
+        .map_err(Error::from_decoding)?;
+
+    let mime = match format {
+        ImageFormat::Gif => "image/gif",
+        ImageFormat::Ico => "image/x-icon",
+        format => Format::from_image_format(format)
+            .map(Format::mime)
+            .unwrap_or("application/octet-stream"),
+    };
+
+    Ok(ImageInfo {
+        extension: format.extensions_str().first().copied().unwrap_or("bin"),
+        mime,
+        width,
+        height,
+    })
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Buffer, Error> {
+    decode_with(bytes, &Limits::default())
+}
+
+pub fn decode_with(bytes: &[u8], limits: &Limits) -> Result<Buffer, Error> {
+    Ok(load(bytes, limits)?.into_rgba8())
+}
+
+pub(crate) fn load(bytes: &[u8], limits: &Limits) -> Result<DynamicImage, Error> {
+    // checked on the header first, for a clearer message than the one of the decoder
+    let info = inspect(bytes)?;
+    limits.check(info.width, info.height)?;
+
+    let format = image::guess_format(bytes).map_err(Error::from_decoding)?;
+    let mut reader = ImageReader::with_format(Cursor::new(bytes), format);
+    reader.limits(limits.decoder_limits());
+    reader.decode().map_err(Error::from_decoding)
+}
+
+pub fn encode(buffer: &Buffer, format: Format) -> Result<Vec<u8>, Error> {
+    encode_with(buffer, format, &EncodeOptions::default())
+}
+
+pub fn encode_with(buffer: &Buffer, format: Format, options: &EncodeOptions) -> Result<Vec<u8>, Error> {
+    if !(1..=100).contains(&options.quality) {
+        return Err(Error::invalid_parameter("quality", format!("{} isn't between 1 and 100", options.quality)));
+    }
+
+    let (width, height) = buffer.dimensions();
+    let mut bytes = Cursor::new(Vec::new());
+
+    let encoded = match format {
+        Format::Png => {
+            let compression = match options.compression {
+                Compression::Fast => CompressionType::Fast,
+                Compression::Default => CompressionType::Default,
+                Compression::Best => CompressionType::Best,
+            };
+
+            PngEncoder::new_with_quality(&mut bytes, compression, FilterType::Sub)
+                .write_image(buffer, width, height, ExtendedColorType::Rgba8)
+        },
+        Format::Jpeg => {
+            // jpeg doesn't have any alpha channel
+            let rgb = DynamicImage::ImageRgba8(buffer.clone()).into_rgb8();
+            JpegEncoder::new_with_quality(&mut bytes, options.quality)
+                .write_image(&rgb, width, height, ExtendedColorType::Rgb8)
+        },
+        Format::Bmp => BmpEncoder::new(&mut bytes)
+            .write_image(buffer, width, height, ExtendedColorType::Rgba8),
+        Format::Tiff => TiffEncoder::new(&mut bytes)
+            .write_image(buffer, width, height, ExtendedColorType::Rgba8),
+        // lossless, the only webp encoder of `image`
+        Format::WebP => WebPEncoder::new_lossless(&mut bytes)
+            .write_image(buffer, width, height, ExtendedColorType::Rgba8),
+    };
+
+    encoded.map_err(Error::from_encoding)?;
+    Ok(bytes.into_inner())
+}
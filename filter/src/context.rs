@@ -0,0 +1,45 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::Error;
+
+// shared between the thread running an algorithm and the ones who may cancel it
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// follows a running algorithm, the default one does nothing
+#[derive(Default)]
+pub struct ProcessContext {
+    // called with the rows done, and the total number of rows, after each row
+    pub progress: Option<Arc<dyn Fn(u32, u32) + Send + Sync>>,
+    // checked before each row, the algorithm stops with `Error::Cancelled` once cancelled
+    pub cancel: Option<CancelToken>,
+}
+
+impl ProcessContext {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(CancelToken::is_cancelled)
+    }
+
+    pub(crate) fn check(&self) -> Result<(), Error> {
+        match self.is_cancelled() {
+            true => Err(Error::Cancelled),
+            false => Ok(()),
+        }
+    }
+
+    pub(crate) fn row_done(&self, done: u32, total: u32) {
+        if let Some(progress) = &self.progress {
+            progress(done, total);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use image::ImageError;
+
+#[derive(Debug)]
+pub enum Error {
+    Decode(ImageError),
+    Encode(ImageError),
+    InvalidParameter { parameter: &'static str, reason: String },
+    UnknownAlgorithm(String),
+    DimensionMismatch { expected: (u32, u32), found: (u32, u32) },
+    UnsupportedFormat(String),
+    TooLarge(String),
+    Cancelled,
+    Io(std::io::Error),
+}
+
+impl Error {
+    pub fn invalid_parameter(parameter: &'static str, reason: impl Into<String>) -> Self {
+        Self::InvalidParameter { parameter, reason: reason.into() }
+    }
+
+    pub(crate) fn from_decoding(err: ImageError) -> Self {
+        match err {
+            ImageError::Unsupported(e) => Self::UnsupportedFormat(e.to_string()),
+            ImageError::Limits(e) => Self::TooLarge(e.to_string()),
+            // decoding from memory, so an io error is a truncated image, not a failing disk
+            e => Self::Decode(e),
+        }
+    }
+
+    pub(crate) fn from_encoding(err: ImageError) -> Self {
+        match err {
+            ImageError::Unsupported(e) => Self::UnsupportedFormat(e.to_string()),
+            ImageError::IoError(e) => Self::Io(e),
+            e => Self::Encode(e),
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Decode(e) => write!(f, "Failed to decode the image: {}", e),
+            Error::Encode(e) => write!(f, "Failed to encode the image: {}", e),
+            Error::InvalidParameter { parameter, reason } => write!(f, "Invalid \"{}\": {}", parameter, reason),
+            Error::UnknownAlgorithm(name) => write!(f, "\"{}\" isn't a valid algorithm name.", name),
+            Error::DimensionMismatch { expected, found } => write!(f,
+                "Expected an image of {}x{} pixels, found {}x{}.", expected.0, expected.1, found.0, found.1
+            ),
+            Error::UnsupportedFormat(e) => write!(f, "Unsupported image format: {}", e),
+            Error::TooLarge(e) => write!(f, "Image too large: {}", e),
+            Error::Cancelled => write!(f, "The processing was cancelled."),
+            Error::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Decode(e) | Error::Encode(e) => Some(e),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
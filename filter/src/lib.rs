@@ -1,17 +1,27 @@
 mod pixel;
 mod algo;
+mod codec;
+mod context;
+mod error;
 
 pub use algo::{
-    Buffer, Algorithms, run_algo, compare_images,
+    Buffer, Algorithms, open, save, process, process_with, process_pipeline, run_algo, compare_images, blend_images,
     compare::compare,
+    blend::{blend, BlendMode},
+    roi::Roi,
+    params::{Parameter, ParameterType},
+    registry::{registry, AlgorithmInfo},
 
-    local_contrast::local_contrast,
-    median_blur::median_blur,
-    min_max::min_max,
-    dilate::dilate,
-    erode::erode,
-    blur::blur,
+    local_contrast::{local_contrast, local_contrast_with},
+    median_blur::{median_blur, median_blur_with},
+    min_max::{min_max, min_max_with},
+    dilate::{dilate, dilate_with},
+    erode::{erode, erode_with},
+    blur::{blur, blur_with},
 };
 
-// reexport for imgerror
-pub use image::ImageError;
+pub use codec::{Format, Compression, EncodeOptions, ImageInfo, Limits, inspect, decode, decode_with, encode, encode_with};
+pub use context::{CancelToken, ProcessContext};
+pub use error::Error;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -15,23 +15,25 @@ pub fn as_gray(pix: [u8; 4]) -> u8 {
     pix[2] / 10       // 0.114
 }
 
+// the sums of large images overflow a u32. they wrap around, and the sum of a window,
+// small enough to fit, still comes out right
 #[inline]
 pub fn add(lhs: [u32; 4], rhs: [u32; 4]) -> [u32; 4] {
     [
-        lhs[0] + rhs[0],
-        lhs[1] + rhs[1],
-        lhs[2] + rhs[2],
-        lhs[3] + rhs[3],
+        lhs[0].wrapping_add(rhs[0]),
+        lhs[1].wrapping_add(rhs[1]),
+        lhs[2].wrapping_add(rhs[2]),
+        lhs[3].wrapping_add(rhs[3]),
     ]
 }
 
 #[inline]
 pub fn sub(lhs: [u32; 4], rhs: [u32; 4]) -> [u32; 4] {
     [
-        lhs[0] - rhs[0],
-        lhs[1] - rhs[1],
-        lhs[2] - rhs[2],
-        lhs[3] - rhs[3],
+        lhs[0].wrapping_sub(rhs[0]),
+        lhs[1].wrapping_sub(rhs[1]),
+        lhs[2].wrapping_sub(rhs[2]),
+        lhs[3].wrapping_sub(rhs[3]),
     ]
 }
 
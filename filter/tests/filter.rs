@@ -65,3 +65,324 @@ fn min_max() {
     let computed = filter::min_max(&source, 1);
     compare_buffer(computed, expected, "min_max.png");
 }
+
+#[test]
+fn roi_rect() {
+    let (source, expected) = open_files("tests/images/grid.png", "tests/expected/blur.png");
+    let roi = filter::Roi::Rect { x: 4, y: 4, width: 8, height: 8 };
+    let computed = filter::process(&source, &filter::Algorithms::Blur(1), Some(&roi)).unwrap();
+
+    for (x, y, pix) in computed.enumerate_pixels() {
+        let inside = (4..12).contains(&x) && (4..12).contains(&y);
+        let target = if inside { &expected } else { &source };
+        assert_eq!(pix, target.get_pixel(x, y), "pixel ({}, {}) inside roi: {}", x, y, inside);
+    }
+}
+
+#[test]
+fn roi_mask() {
+    let (source, expected) = open_files("tests/images/grid.png", "tests/expected/blur.png");
+    let (width, height) = source.dimensions();
+    let mask = image::GrayImage::from_fn(width, height, |x, _| image::Luma([if x < width / 2 { 0 } else { 255 }]));
+    let computed = filter::process(&source, &filter::Algorithms::Blur(1), Some(&filter::Roi::Mask(mask))).unwrap();
+
+    for (x, y, pix) in computed.enumerate_pixels() {
+        let target = if x < width / 2 { &source } else { &expected };
+        assert_eq!(pix, target.get_pixel(x, y), "pixel ({}, {})", x, y);
+    }
+}
+
+#[test]
+fn roi_out_of_bounds() {
+    let (source, _) = open_files("tests/images/grid.png", "tests/expected/blur.png");
+    let roi = filter::Roi::Rect { x: source.width() - 1, y: 0, width: 2, height: 1 };
+    let err = filter::process(&source, &filter::Algorithms::Blur(1), Some(&roi)).err().unwrap();
+    assert!(matches!(err, filter::Error::InvalidParameter { parameter: "roi", .. }));
+}
+
+#[test]
+fn blend_difference() {
+    let (source, _) = open_files("tests/images/noise.png", "tests/expected/blur.png");
+    let computed = filter::blend(&source, &source, filter::BlendMode::Difference, 1.).unwrap();
+
+    for (pix, orig) in computed.pixels().zip(source.pixels()) {
+        assert_eq!(pix.0[..3], [0; 3]);
+        assert_eq!(pix.0[3], orig.0[3]);
+    }
+}
+
+#[test]
+fn blend_opacity() {
+    let (base, layer) = open_files("tests/images/noise.png", "tests/expected/median_blur.png");
+    let transparent = filter::blend(&base, &layer, filter::BlendMode::Normal, 0.).unwrap();
+    compare_buffer(transparent, base.clone(), "blend_transparent.png");
+
+    let opaque = filter::blend(&base, &layer, filter::BlendMode::Normal, 1.).unwrap();
+    compare_buffer(opaque, layer, "blend_opaque.png");
+}
+
+#[test]
+fn blend_mismatch_size() {
+    let (base, _) = open_files("tests/images/noise.png", "tests/expected/blur.png");
+    let layer = filter::Buffer::new(1, 1);
+    let err = filter::blend(&base, &layer, filter::BlendMode::Multiply, 1.).unwrap_err();
+    assert!(matches!(err, filter::Error::DimensionMismatch { found: (1, 1), .. }));
+}
+
+#[test]
+fn parameters_out_of_range() {
+    let (source, _) = open_files("tests/images/grid.png", "tests/expected/blur.png");
+
+    let err = filter::process(&source, &filter::Algorithms::MedianBlur(u32::MAX), None).err().unwrap();
+    assert!(matches!(err, filter::Error::InvalidParameter { parameter: "radius", .. }));
+
+    let err = filter::process(&source, &filter::Algorithms::LocalContrast(1, -256), None).err().unwrap();
+    assert!(matches!(err, filter::Error::InvalidParameter { parameter: "factor", .. }));
+
+    let err = filter::process(&filter::Buffer::new(0, 0), &filter::Algorithms::Blur(1), None).err().unwrap();
+    assert!(matches!(err, filter::Error::InvalidParameter { parameter: "image", .. }));
+}
+
+#[test]
+fn registry() {
+    for info in filter::registry() {
+        let algo = filter::Algorithms::try_from(info.name).unwrap();
+        assert_eq!(algo, info.default);
+        assert_eq!(algo.to_string(), info.display_name);
+        assert!(algo.validate().is_ok(), "{} defaults are out of range", info.name);
+    }
+
+    assert_eq!(filter::Algorithms::LocalContrast(3, 5).arguments(), vec![("radius", 3), ("factor", 5)]);
+    assert_eq!(filter::Algorithms::Blur(2).arguments(), vec![("radius", 2)]);
+
+    let err = filter::Algorithms::try_from("sharpen").unwrap_err();
+    assert!(matches!(err, filter::Error::UnknownAlgorithm(name) if name == "sharpen"));
+}
+
+#[test]
+fn radius_above_size() {
+    let sizes = [(1, 1), (3, 3), (1, 5), (5, 1)];
+
+    for info in filter::registry() {
+        let radius = info.parameters.iter().find(|parameter| parameter.name == "radius").unwrap();
+        for value in [2, 5, radius.max as u32] {
+            let mut algo = info.default;
+            algo.set_radius(value);
+
+            for (width, height) in sizes {
+                let source = filter::Buffer::from_fn(width, height, |x, y| image::Rgba([(x * 50) as u8, (y * 50) as u8, 0, 255]));
+                let computed = filter::process(&source, &algo, None).unwrap();
+                assert_eq!(computed.dimensions(), (width, height), "{} with a radius of {}", info.name, value);
+                // a single pixel is its whole neighbourhood
+                if (width, height) == (1, 1) {
+                    assert_eq!(computed, source, "{} with a radius of {}", info.name, value);
+                }
+            }
+        }
+    }
+
+    // the whole image is the neighbourhood
+    let source = filter::Buffer::from_fn(3, 3, |x, y| image::Rgba([(x * 3 + y) as u8 * 10, 0, 0, 255]));
+    let computed = filter::process(&source, &filter::Algorithms::Dilate(5), None).unwrap();
+    assert!(computed.pixels().all(|pix| pix.0 == [80, 0, 0, 255]));
+    let computed = filter::process(&source, &filter::Algorithms::Erode(5), None).unwrap();
+    assert!(computed.pixels().all(|pix| pix.0 == [0, 0, 0, 255]));
+
+    let source = filter::Buffer::from_pixel(1, 1, image::Rgba([255, 0, 0, 255]));
+    let computed = filter::process(&source, &filter::Algorithms::MinMax(3), None).unwrap();
+    assert_eq!(computed.get_pixel(0, 0).0, [255, 0, 0, 255]);
+}
+
+#[test]
+fn encode_decode() {
+    let (source, _) = open_files("tests/images/noise.png", "tests/expected/blur.png");
+
+    for format in [filter::Format::Png, filter::Format::Bmp, filter::Format::Tiff, filter::Format::WebP] {
+        let bytes = filter::encode(&source, format).unwrap();
+        assert_eq!(filter::Format::guess(&bytes), Some(format));
+        compare_buffer(filter::decode(&bytes).unwrap(), source.clone(), "encode_decode.png");
+    }
+
+    let bytes = filter::encode(&source, filter::Format::Jpeg).unwrap();
+    assert_eq!(filter::decode(&bytes).unwrap().dimensions(), source.dimensions());
+}
+
+#[test]
+fn encode_options() {
+    let (source, _) = open_files("tests/images/noise.png", "tests/expected/blur.png");
+    let low = filter::EncodeOptions { quality: 10, ..Default::default() };
+    let high = filter::EncodeOptions { quality: 100, ..Default::default() };
+
+    let low = filter::encode_with(&source, filter::Format::Jpeg, &low).unwrap();
+    let high = filter::encode_with(&source, filter::Format::Jpeg, &high).unwrap();
+    assert!(low.len() < high.len());
+
+    let invalid = filter::EncodeOptions { quality: 0, ..Default::default() };
+    let err = filter::encode_with(&source, filter::Format::Jpeg, &invalid).unwrap_err();
+    assert!(matches!(err, filter::Error::InvalidParameter { parameter: "quality", .. }));
+}
+
+// a png declaring `width`x`height` pixels, without any pixel data
+fn png_header(width: u32, height: u32) -> Vec<u8> {
+    fn crc32(bytes: &[u8]) -> u32 {
+        !bytes.iter().fold(!0u32, |crc, byte| {
+            (0..8).fold(crc ^ *byte as u32, |crc, _| (crc >> 1) ^ (0xEDB8_8320 & (!(crc & 1)).wrapping_add(1)))
+        })
+    }
+
+    fn chunk(png: &mut Vec<u8>, kind: &[u8], data: &[u8]) {
+        let content = [kind, data].concat();
+        png.extend((data.len() as u32).to_be_bytes());
+        png.extend(&content);
+        png.extend(crc32(&content).to_be_bytes());
+    }
+
+    let mut ihdr = Vec::new();
+    ihdr.extend(width.to_be_bytes());
+    ihdr.extend(height.to_be_bytes());
+    // 8 bits rgba, no interlacing
+    ihdr.extend([8, 6, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    chunk(&mut png, b"IHDR", &ihdr);
+    chunk(&mut png, b"IDAT", &[]);
+    chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+#[test]
+fn decode_limits() {
+    let bomb = png_header(60000, 60000);
+    assert_eq!(filter::inspect(&bomb).unwrap().width, 60000);
+
+    let err = filter::decode(&bomb).unwrap_err();
+    assert!(matches!(err, filter::Error::TooLarge(_)));
+
+    let limits = filter::Limits { max_width: 100, max_height: 100, max_pixels: 50 * 50 };
+    let err = filter::decode_with(&png_header(101, 1), &limits).unwrap_err();
+    assert!(matches!(err, filter::Error::TooLarge(_)));
+    let err = filter::decode_with(&png_header(60, 60), &limits).unwrap_err();
+    assert!(matches!(err, filter::Error::TooLarge(_)));
+
+    let mask = filter::Roi::from_mask_bytes(&bomb, &filter::Limits::default());
+    assert!(matches!(mask, Err(filter::Error::TooLarge(_))));
+
+    let (source, _) = open_files("tests/images/noise.png", "tests/expected/blur.png");
+    let bytes = filter::encode(&source, filter::Format::Png).unwrap();
+    let (width, height) = source.dimensions();
+    let exact = filter::Limits { max_width: width, max_height: height, max_pixels: (width * height) as u64 };
+    assert_eq!(filter::decode_with(&bytes, &exact).unwrap().dimensions(), (width, height));
+
+    // the decoder has room for pixels larger than the ones of the result
+    let wide = image::ImageBuffer::<image::Rgba<u16>, _>::from_pixel(width, height, image::Rgba([u16::MAX; 4]));
+    let mut bytes = std::io::Cursor::new(Vec::new());
+    wide.write_to(&mut bytes, image::ImageFormat::Png).unwrap();
+    assert_eq!(filter::decode_with(bytes.get_ref(), &exact).unwrap().dimensions(), (width, height));
+}
+
+#[test]
+fn process_progress() {
+    use std::sync::{Arc, Mutex};
+
+    let source = image::open("tests/images/grid.png").unwrap().into_rgba8();
+    let algorithms = [
+        filter::Algorithms::Blur(1),
+        filter::Algorithms::Dilate(1),
+        filter::Algorithms::Erode(1),
+        filter::Algorithms::LocalContrast(1, 2),
+        filter::Algorithms::MedianBlur(1),
+        filter::Algorithms::MinMax(1),
+    ];
+
+    for algo in algorithms.iter() {
+        let rows = Arc::new(Mutex::new(Vec::new()));
+        let ctx = filter::ProcessContext {
+            progress: Some(Arc::new({
+                let rows = rows.clone();
+                move |done, total| rows.lock().unwrap().push((done, total))
+            })),
+            cancel: None,
+        };
+
+        let computed = filter::process_with(&source, algo, None, &ctx).unwrap();
+        assert!(computed == filter::process(&source, algo, None).unwrap(), "{}", algo);
+
+        // every row, in order
+        let expected = (1..=source.height()).map(|row| (row, source.height())).collect::<Vec<_>>();
+        assert_eq!(*rows.lock().unwrap(), expected, "{}", algo);
+    }
+}
+
+#[test]
+fn process_cancel() {
+    let source = image::open("tests/images/grid.png").unwrap().into_rgba8();
+    let cancel = filter::CancelToken::default();
+
+    // cancelled halfway through
+    let ctx = filter::ProcessContext {
+        progress: Some(std::sync::Arc::new({
+            let cancel = cancel.clone();
+            let half = source.height() / 2;
+            move |done, _| if done == half { cancel.cancel() }
+        })),
+        cancel: Some(cancel.clone()),
+    };
+
+    let err = filter::process_with(&source, &filter::Algorithms::MedianBlur(2), None, &ctx).unwrap_err();
+    assert!(matches!(err, filter::Error::Cancelled));
+    assert!(ctx.is_cancelled());
+
+    let err = filter::blur_with(&source, 1, &ctx).unwrap_err();
+    assert!(matches!(err, filter::Error::Cancelled));
+}
+
+#[test]
+fn pipeline() {
+    use std::sync::{Arc, Mutex};
+
+    let source = image::open("tests/images/grid.png").unwrap().into_rgba8();
+    let steps = [filter::Algorithms::MedianBlur(2), filter::Algorithms::LocalContrast(8, 2)];
+    let rows = Arc::new(Mutex::new(Vec::new()));
+    let ctx = filter::ProcessContext {
+        progress: Some(Arc::new({
+            let rows = rows.clone();
+            move |done, total| rows.lock().unwrap().push((done, total))
+        })),
+        cancel: None,
+    };
+
+    let computed = filter::process_pipeline(&source, &steps, None, &ctx).unwrap();
+    let expected = filter::local_contrast(&filter::median_blur(&source, 2), 8, 2);
+    compare_buffer(computed, expected, "pipeline.png");
+
+    // the rows of both steps, as a single progress
+    let height = source.height();
+    let expected = (1..=height * 2).map(|row| (row, height * 2)).collect::<Vec<_>>();
+    assert_eq!(*rows.lock().unwrap(), expected);
+
+    // the roi applies to the result of the whole pipeline
+    let roi = filter::Roi::Rect { x: 0, y: 0, width: 4, height: 4 };
+    let computed = filter::process_pipeline(&source, &steps, Some(&roi), &filter::ProcessContext::default()).unwrap();
+    assert_eq!(computed.get_pixel(10, 10), source.get_pixel(10, 10));
+
+    // nothing runs with an invalid step
+    let steps = [filter::Algorithms::Blur(2), filter::Algorithms::MedianBlur(u32::MAX)];
+    let err = filter::process_pipeline(&source, &steps, None, &filter::ProcessContext::default()).unwrap_err();
+    assert!(matches!(err, filter::Error::InvalidParameter { parameter: "radius", .. }));
+
+    let err = filter::process_pipeline(&source, &[], None, &filter::ProcessContext::default()).unwrap_err();
+    assert!(matches!(err, filter::Error::InvalidParameter { parameter: "steps", .. }));
+}
+
+#[test]
+fn large_sums() {
+    // the sums of a white image this size don't fit in a u32, but it's within the default limits
+    let (width, height) = (5000, 4000);
+    assert!(filter::Limits::default().check(width, height).is_ok());
+    let source = filter::Buffer::from_pixel(width, height, image::Rgba([255; 4]));
+
+    for algo in [filter::Algorithms::Blur(2), filter::Algorithms::LocalContrast(2, 3)] {
+        let computed = filter::process(&source, &algo, None).unwrap();
+        assert!(computed.pixels().all(|pix| pix.0 == [255; 4]), "{}", algo);
+    }
+}
@@ -0,0 +1,220 @@
+use crate::error::CliError;
+use crate::inputs;
+use crate::parallel;
+use crate::progress::Progress;
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+use filter::{Algorithms, Compression, EncodeOptions, Format, ProcessContext, Roi};
+
+#[derive(Args)]
+pub struct ApplyArgs {
+    /// Name of the algorithm, see `multy algorithms`
+    algorithm: String,
+    /// Default to 1
+    #[clap(long)]
+    radius: Option<u32>,
+    /// Only for the algorithms taking a factor, default to 2
+    #[clap(long, allow_hyphen_values = true)]
+    factor: Option<i32>,
+    /// Another algorithm applied on the result, ex: `local_contrast:radius=8,factor=2`. Can be repeated
+    #[clap(long, value_name = "STEP")]
+    then: Vec<String>,
+    /// Only process the rectangle `x,y,width,height`
+    #[clap(long, conflicts_with = "mask")]
+    roi: Option<String>,
+    /// Grayscale image of the same size, black keeps the original pixels and white takes the processed ones
+    #[clap(long)]
+    mask: Option<PathBuf>,
+    /// Format of the processed images, by default the one of the output, or of the input
+    #[clap(long)]
+    format: Option<String>,
+    /// Jpeg quality, between 1 and 100
+    #[clap(long)]
+    quality: Option<u8>,
+    /// Png compression, among fast, default and best
+    #[clap(long)]
+    compression: Option<String>,
+    /// Output file for a single image, otherwise a directory. By default, next to the inputs
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+    /// Number of images processed at the same time, by default one per cpu
+    #[clap(short, long)]
+    jobs: Option<usize>,
+    /// Don't show the progress
+    #[clap(short, long)]
+    quiet: bool,
+    /// Images, directories of images or glob patterns
+    #[clap(required = true)]
+    inputs: Vec<String>,
+}
+
+// where an image goes
+struct Task {
+    input: PathBuf,
+    output: PathBuf,
+    format: Format,
+}
+
+// the exit code, 1 when an image failed
+pub fn run(args: ApplyArgs) -> Result<i32, CliError> {
+    let steps = steps(&args)?;
+    let roi = match (&args.roi, &args.mask) {
+        (Some(roi), _) => Some(parse_roi(roi)?),
+        (None, Some(mask)) => Some(Roi::from_mask(mask)?),
+        (None, None) => None,
+    };
+    let format = args.format.as_deref().map(Format::try_from).transpose()?;
+    let mut options = EncodeOptions::default();
+    if let Some(quality) = args.quality {
+        options.quality = quality;
+    }
+    if let Some(compression) = &args.compression {
+        options.compression = Compression::try_from(compression.as_str())?;
+    }
+
+    let inputs = inputs::expand(&args.inputs)?;
+    let tasks = plan(&inputs, args.output.as_deref(), format, &steps)?;
+
+    let progress = Progress::new(args.quiet, tasks.len());
+    let results = parallel::map(&tasks, args.jobs.unwrap_or_else(parallel::default_jobs), |task| {
+        let name = task.input.display().to_string();
+        let result = apply_file(task, &steps, roi.as_ref(), &options, &progress.context(&name));
+        progress.file_done(&name);
+        result
+    });
+    progress.finish();
+
+    let mut code = 0;
+    for (task, result) in tasks.iter().zip(results) {
+        if let Err(e) = result {
+            progress.message(&format!("error: {}: {}", task.input.display(), e));
+            code = 1;
+        }
+    }
+
+    Ok(code)
+}
+
+fn apply_file(task: &Task, steps: &[Algorithms], roi: Option<&Roi>, options: &EncodeOptions, ctx: &ProcessContext) -> Result<(), CliError> {
+    let bytes = fs::read(&task.input).map_err(CliError::io(&task.input))?;
+    let img = filter::decode(&bytes)?;
+    let buffer = filter::process_pipeline(&img, steps, roi, ctx)?;
+    let bytes = filter::encode_with(&buffer, task.format, options)?;
+
+    fs::write(&task.output, bytes).map_err(CliError::io(&task.output))
+}
+
+// the algorithm and its options, followed by every `--then`.
+// checked before any image is read, a parameter out of range being a usage error
+fn steps(args: &ApplyArgs) -> Result<Vec<Algorithms>, CliError> {
+    let mut algo = Algorithms::try_from(args.algorithm.as_str())?;
+    if let Some(radius) = args.radius {
+        algo.set_radius(radius);
+    }
+    if let Some(factor) = args.factor {
+        set_factor(&mut algo, factor)?;
+    }
+
+    let mut steps = vec![algo];
+    for step in &args.then {
+        steps.push(parse_step(step)?);
+    }
+    steps.iter().try_for_each(Algorithms::validate)?;
+
+    Ok(steps)
+}
+
+// ex: "local_contrast:radius=8,factor=2", or just "blur" for the default parameters
+pub fn parse_step(step: &str) -> Result<Algorithms, CliError> {
+    let (name, parameters) = step.split_once(':').unwrap_or((step, ""));
+    let mut algo = Algorithms::try_from(name)?;
+
+    for parameter in parameters.split(',').filter(|parameter| !parameter.is_empty()) {
+        let (key, value) = parameter.split_once('=')
+            .ok_or_else(|| CliError::usage(format!("\"{}\" should be formatted as \"name=value\"", parameter)))?;
+        let invalid = |e: std::num::ParseIntError| CliError::usage(format!("Invalid \"{}\": {}", key, e));
+
+        match key {
+            "radius" => algo.set_radius(value.parse().map_err(invalid)?),
+            "factor" => set_factor(&mut algo, value.parse().map_err(invalid)?)?,
+            _ => return Err(CliError::usage(format!("\"{}\" has no parameter \"{}\"", name, key))),
+        }
+    }
+
+    Ok(algo)
+}
+
+fn set_factor(algo: &mut Algorithms, factor: i32) -> Result<(), CliError> {
+    if !algo.need_factor() {
+        return Err(CliError::usage(format!("\"{}\" has no factor", algo.info().name)));
+    }
+
+    algo.set_factor(factor);
+    Ok(())
+}
+
+fn parse_roi(roi: &str) -> Result<Roi, CliError> {
+    let values = roi.split(',')
+        .map(|value| value.trim().parse::<u32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| CliError::usage(format!("Invalid \"roi\": {}", e)))?;
+
+    match values[..] {
+        [x, y, width, height] => Ok(Roi::Rect { x, y, width, height }),
+        _ => Err(CliError::usage("\"roi\" must be formatted as \"x,y,width,height\"")),
+    }
+}
+
+fn plan(inputs: &[PathBuf], output: Option<&Path>, format: Option<Format>, steps: &[Algorithms]) -> Result<Vec<Task>, CliError> {
+    let into_dir = match output {
+        Some(output) => inputs.len() > 1 || output.is_dir(),
+        None => false,
+    };
+    if let (true, Some(dir)) = (into_dir, output) {
+        fs::create_dir_all(dir).map_err(CliError::io(dir))?;
+    }
+
+    let mut taken = HashSet::new();
+    let suffix = steps.iter().map(|step| step.info().name).collect::<Vec<_>>().join("-");
+
+    inputs.iter().map(|input| {
+        let stem = input.file_stem().and_then(|stem| stem.to_str()).unwrap_or("image");
+
+        let task = match output {
+            Some(output) if !into_dir => {
+                let format = format.or_else(|| inputs::format(output)).unwrap_or_else(|| keep_format(input));
+                Task { input: input.clone(), output: output.to_owned(), format }
+            },
+            Some(dir) => {
+                let format = format.unwrap_or_else(|| keep_format(input));
+                // two inputs can't overwrite each other, ex: lena.png and lena.jpg
+                let mut output = dir.join(format!("{}.{}", stem, format.extension()));
+                let mut n = 1;
+                while !taken.insert(output.clone()) {
+                    n += 1;
+                    output = dir.join(format!("{}-{}.{}", stem, n, format.extension()));
+                }
+                Task { input: input.clone(), output, format }
+            },
+            None => {
+                let format = format.unwrap_or_else(|| keep_format(input));
+                let output = input.with_file_name(format!("{}-{}.{}", stem, suffix, format.extension()));
+                Task { input: input.clone(), output, format }
+            },
+        };
+
+        if task.input == task.output {
+            return Err(CliError::usage(format!("{} would be overwritten", task.input.display())));
+        }
+        Ok(task)
+    }).collect()
+}
+
+// the format of the input when it's an image format, png otherwise
+fn keep_format(input: &Path) -> Format {
+    inputs::format(input).unwrap_or(Format::Png)
+}
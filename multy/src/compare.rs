@@ -0,0 +1,163 @@
+use crate::error::CliError;
+use crate::inputs;
+use crate::parallel;
+use crate::progress::Progress;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+use serde_json::{json, Value};
+
+#[derive(Args)]
+pub struct CompareArgs {
+    /// Image, or directory of images compared by name with the ones of `right`
+    left: PathBuf,
+    right: PathBuf,
+    /// Where to save the differences in red, a directory when comparing directories
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+    /// Print the report as JSON
+    #[clap(long)]
+    json: bool,
+    /// Number of images compared at the same time, by default one per cpu
+    #[clap(short, long)]
+    jobs: Option<usize>,
+    /// Don't show the progress
+    #[clap(short, long)]
+    quiet: bool,
+}
+
+struct Pair {
+    left: PathBuf,
+    right: PathBuf,
+    output: Option<PathBuf>,
+}
+
+struct Report {
+    width: u32,
+    height: u32,
+    different_pixels: u64,
+}
+
+// the exit code, like `diff`: 0 when the images are the same, 1 when they differ and 2 on errors
+pub fn run(args: CompareArgs) -> Result<i32, CliError> {
+    let pairs = pairs(&args)?;
+    let single = !args.left.is_dir();
+
+    let progress = Progress::new(args.quiet, pairs.len());
+    let results = parallel::map(&pairs, args.jobs.unwrap_or_else(parallel::default_jobs), |pair| {
+        let result = compare_pair(pair);
+        progress.file_done(&pair.left.display().to_string());
+        result
+    });
+    progress.finish();
+
+    let mut code = 0;
+    let mut reports = Vec::new();
+    for (pair, result) in pairs.iter().zip(results) {
+        let mut report = json!({
+            "left": pair.left,
+            "right": pair.right,
+        });
+
+        match result {
+            Ok(result) => {
+                let total = result.width as u64 * result.height as u64;
+                if result.different_pixels > 0 {
+                    code = code.max(1);
+                }
+                if !args.json {
+                    println!("{}", summary(pair, &result));
+                }
+
+                report["width"] = json!(result.width);
+                report["height"] = json!(result.height);
+                report["different_pixels"] = json!(result.different_pixels);
+                report["total_pixels"] = json!(total);
+                report["ratio"] = json!(result.different_pixels as f64 / total as f64);
+                report["output"] = json!(pair.output);
+            },
+            Err(e) => {
+                code = 2;
+                if !args.json {
+                    progress.message(&format!("error: {} {}: {}", pair.left.display(), pair.right.display(), e));
+                }
+
+                report["error"] = json!(e.to_string());
+            },
+        }
+
+        reports.push(report);
+    }
+
+    if args.json {
+        let report = match single {
+            true => reports.remove(0),
+            false => Value::from(reports),
+        };
+        println!("{}", serde_json::to_string_pretty(&report).expect("a report is valid JSON"));
+    }
+
+    Ok(code)
+}
+
+fn compare_pair(pair: &Pair) -> Result<Report, CliError> {
+    let left = filter::open(&pair.left)?;
+    let right = filter::open(&pair.right)?;
+    let diff = filter::compare(&left, &right)?;
+
+    let different_pixels = left.pixels()
+        .zip(right.pixels())
+        .filter(|(lhs, rhs)| lhs != rhs)
+        .count() as u64;
+
+    if let Some(output) = &pair.output {
+        filter::save(&diff, output)?;
+    }
+
+    Ok(Report { width: left.width(), height: left.height(), different_pixels })
+}
+
+fn summary(pair: &Pair, report: &Report) -> String {
+    let total = report.width as u64 * report.height as u64;
+
+    match report.different_pixels {
+        0 => format!("{} {}: identical", pair.left.display(), pair.right.display()),
+        different => format!(
+            "{} {}: {} of {} pixels differ ({:.2}%)",
+            pair.left.display(), pair.right.display(), different, total, different as f64 * 100. / total as f64
+        ),
+    }
+}
+
+// two files, or the images with the same name in two directories
+fn pairs(args: &CompareArgs) -> Result<Vec<Pair>, CliError> {
+    match (args.left.is_dir(), args.right.is_dir()) {
+        (false, false) => Ok(vec![Pair { left: args.left.clone(), right: args.right.clone(), output: args.output.clone() }]),
+        (true, true) => {
+            if let Some(output) = &args.output {
+                fs::create_dir_all(output).map_err(CliError::io(output))?;
+            }
+
+            let mut both = names(&args.left)?;
+            both.extend(names(&args.right)?);
+            both.sort();
+            both.dedup();
+
+            Ok(both.into_iter().map(|name| Pair {
+                left: args.left.join(&name),
+                right: args.right.join(&name),
+                output: args.output.as_ref().map(|output| output.join(Path::new(&name).with_extension("png"))),
+            }).collect())
+        },
+        _ => Err(CliError::usage("compare either two files, or two directories")),
+    }
+}
+
+fn names(dir: &Path) -> Result<Vec<String>, CliError> {
+    Ok(inputs::images(dir)?
+        .into_iter()
+        .filter_map(|path| path.file_name().and_then(|name| name.to_str()).map(String::from))
+        .collect())
+}
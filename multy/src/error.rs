@@ -0,0 +1,38 @@
+use std::{fmt::Display, io, path::{Path, PathBuf}};
+
+#[derive(Debug)]
+pub enum CliError {
+    Filter(filter::Error),
+    Io { path: PathBuf, error: io::Error },
+    // wrong arguments, that clap can't check by itself
+    Usage(String),
+}
+
+impl CliError {
+    pub fn usage(message: impl Into<String>) -> Self {
+        CliError::Usage(message.into())
+    }
+
+    // ex: `fs::read(path).map_err(CliError::io(path))`
+    pub fn io(path: &Path) -> impl FnOnce(io::Error) -> Self + '_ {
+        move |error| CliError::Io { path: path.to_owned(), error }
+    }
+}
+
+impl From<filter::Error> for CliError {
+    fn from(err: filter::Error) -> Self {
+        CliError::Filter(err)
+    }
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CliError::Filter(e) => write!(f, "{}", e),
+            CliError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            CliError::Usage(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
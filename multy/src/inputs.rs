@@ -0,0 +1,58 @@
+use crate::error::CliError;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// the files named by the arguments: files as they are, the images of directories,
+// and the matches of glob patterns, for the shells that don't expand them
+pub fn expand(args: &[String]) -> Result<Vec<PathBuf>, CliError> {
+    let mut files = Vec::new();
+
+    for arg in args {
+        let path = Path::new(arg);
+
+        if path.is_dir() {
+            files.extend(images(path)?);
+        } else if path.exists() {
+            files.push(path.to_owned());
+        } else if arg.contains(['*', '?', '[']) {
+            let pattern = glob::glob(arg).map_err(|e| CliError::usage(format!("\"{}\" isn't a valid pattern: {}", arg, e)))?;
+            let matches = pattern
+                .filter_map(Result::ok)
+                .filter(|path| path.is_file())
+                .collect::<Vec<_>>();
+
+            if matches.is_empty() {
+                return Err(CliError::usage(format!("no file matches \"{}\"", arg)));
+            }
+            files.extend(matches);
+        } else {
+            return Err(CliError::usage(format!("{}: no such file or directory", arg)));
+        }
+    }
+
+    Ok(files)
+}
+
+// the images of a directory, by name
+pub fn images(dir: &Path) -> Result<Vec<PathBuf>, CliError> {
+    let mut images = fs::read_dir(dir).map_err(CliError::io(dir))?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && is_image(path))
+        .collect::<Vec<_>>();
+
+    images.sort();
+    Ok(images)
+}
+
+// from its extension
+pub fn is_image(path: &Path) -> bool {
+    format(path).is_some()
+}
+
+pub fn format(path: &Path) -> Option<filter::Format> {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(|extension| filter::Format::try_from(extension.to_lowercase().as_str()).ok())
+}
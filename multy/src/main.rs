@@ -0,0 +1,60 @@
+mod apply;
+mod compare;
+mod error;
+mod inputs;
+mod parallel;
+mod progress;
+
+use clap::{Parser, Subcommand};
+use std::process;
+
+/// Run the filters of multy on files, without the server
+#[derive(Parser)]
+#[clap(name = "multy", version)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Apply an algorithm, or a pipeline of algorithms, on images
+    Apply(apply::ApplyArgs),
+    /// Compare images pixel by pixel
+    Compare(compare::CompareArgs),
+    /// List the algorithms and their parameters
+    Algorithms,
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let result = match cli.command {
+        Command::Apply(args) => apply::run(args),
+        Command::Compare(args) => compare::run(args),
+        Command::Algorithms => {
+            algorithms();
+            Ok(0)
+        },
+    };
+
+    match result {
+        Ok(code) => process::exit(code),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(2);
+        },
+    }
+}
+
+fn algorithms() {
+    for info in filter::registry() {
+        let parameters = info.parameters.iter()
+            .map(|parameter| format!("{} ({} to {}, default {})", parameter.name, parameter.min, parameter.max, parameter.default))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        println!("{} - {}", info.name, parameters);
+        println!("    {}", info.description);
+    }
+}
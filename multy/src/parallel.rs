@@ -0,0 +1,34 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+// `task` on every item, on `jobs` threads at most. the results keep the order of the items
+pub fn map<T: Sync, R: Send>(items: &[T], jobs: usize, task: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new((0..items.len()).map(|_| None).collect::<Vec<_>>());
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, items.len().max(1)) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let item = match items.get(i) {
+                    Some(item) => item,
+                    None => return,
+                };
+
+                let result = task(item);
+                results.lock().unwrap()[i] = Some(result);
+            });
+        }
+    });
+
+    results.into_inner().unwrap()
+        .into_iter()
+        .map(|result| result.expect("every item is done"))
+        .collect()
+}
+
+// the number of threads when none is given
+pub fn default_jobs() -> usize {
+    thread::available_parallelism().map(usize::from).unwrap_or(1)
+}
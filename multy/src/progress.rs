@@ -0,0 +1,61 @@
+use std::io::{self, IsTerminal, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+// a single line on stderr, redrawn as the work goes, only when stderr is a terminal
+pub struct Progress {
+    enabled: bool,
+    total: usize,
+    done: AtomicUsize,
+}
+
+impl Progress {
+    pub fn new(quiet: bool, total: usize) -> Self {
+        Self { enabled: !quiet && io::stderr().is_terminal(), total, done: AtomicUsize::new(0) }
+    }
+
+    // follows the rows of a single image, the files are counted otherwise
+    pub fn context(&self, name: &str) -> filter::ProcessContext {
+        if !self.enabled || self.total != 1 {
+            return filter::ProcessContext::default();
+        }
+
+        let name = name.to_owned();
+        let last = AtomicU32::new(u32::MAX);
+        let progress = move |done: u32, total: u32| {
+            let percent = done * 100 / total;
+            if last.swap(percent, Ordering::Relaxed) != percent {
+                draw(&format!("{}: {}%", name, percent));
+            }
+        };
+
+        filter::ProcessContext { progress: Some(Arc::new(progress)), cancel: None }
+    }
+
+    pub fn file_done(&self, name: &str) {
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.enabled && self.total > 1 {
+            draw(&format!("[{}/{}] {}", done, self.total, name));
+        }
+    }
+
+    // a message of its own, under the progress
+    pub fn message(&self, message: &str) {
+        if self.enabled {
+            draw("");
+        }
+        eprintln!("{}", message);
+    }
+
+    pub fn finish(&self) {
+        if self.enabled {
+            draw("");
+        }
+    }
+}
+
+fn draw(line: &str) {
+    // clears the rest of the previous line
+    eprint!("\r{}\x1b[K", line);
+    io::stderr().flush().ok();
+}
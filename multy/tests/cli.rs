@@ -0,0 +1,148 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+const GRID: &str = "../filter/tests/images/grid.png";
+const NOISE: &str = "../filter/tests/images/noise.png";
+
+fn multy(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_multy"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn path(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+#[test]
+fn apply() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("out.png");
+
+    let result = multy(&["apply", "blur", "--radius", "3", GRID, "-o", path(&output)]);
+    assert_eq!(result.status.code(), Some(0));
+
+    let source = filter::open(Path::new(GRID)).unwrap();
+    assert_eq!(filter::open(&output).unwrap(), filter::blur(&source, 3));
+}
+
+#[test]
+fn apply_pipeline() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("grid.png");
+    fs::copy(GRID, &input).unwrap();
+
+    let result = multy(&["apply", "median_blur", "--radius", "2", "--then", "local_contrast:radius=8,factor=3", path(&input)]);
+    assert_eq!(result.status.code(), Some(0));
+
+    // named after the steps, next to the input
+    let source = filter::open(&input).unwrap();
+    let expected = filter::local_contrast(&filter::median_blur(&source, 2), 8, 3);
+    let output = dir.path().join("grid-median_blur-local_contrast.png");
+    assert_eq!(filter::open(&output).unwrap(), expected);
+}
+
+#[test]
+fn apply_batch() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs = dir.path().join("inputs");
+    let outputs = dir.path().join("outputs");
+    fs::create_dir(&inputs).unwrap();
+    fs::copy(GRID, inputs.join("grid.png")).unwrap();
+    fs::copy(NOISE, inputs.join("noise.png")).unwrap();
+    fs::write(inputs.join("notes.txt"), "not an image").unwrap();
+
+    // the text file of the directory is skipped
+    let result = multy(&["apply", "erode", "-j", "2", path(&inputs), "-o", path(&outputs)]);
+    assert_eq!(result.status.code(), Some(0));
+    assert!(outputs.join("grid.png").is_file());
+    assert!(outputs.join("noise.png").is_file());
+
+    // a glob pattern, as quoted from the shell, in another format
+    let pattern = inputs.join("*.png");
+    let result = multy(&["apply", "dilate", "--format", "jpeg", path(&pattern), "-o", path(&outputs)]);
+    assert_eq!(result.status.code(), Some(0));
+    assert!(outputs.join("grid.jpg").is_file());
+    assert!(outputs.join("noise.jpg").is_file());
+
+    // an invalid image doesn't stop the others
+    fs::write(inputs.join("broken.png"), "not an image").unwrap();
+    let result = multy(&["apply", "blur", "-q", path(&inputs), "-o", path(&outputs)]);
+    assert_eq!(result.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&result.stderr).contains("broken.png"));
+}
+
+#[test]
+fn apply_errors() {
+    // an unknown algorithm
+    let result = multy(&["apply", "sharpen", GRID]);
+    assert_eq!(result.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&result.stderr).starts_with("error: "));
+
+    // blur has no factor
+    let result = multy(&["apply", "blur", "--factor", "2", GRID]);
+    assert_eq!(result.status.code(), Some(2));
+
+    let result = multy(&["apply", "blur", "--then", "dilate:factor=2", GRID]);
+    assert_eq!(result.status.code(), Some(2));
+
+    // out of range, reported once for all the images
+    let dir = tempfile::tempdir().unwrap();
+    let outputs = dir.path().join("outputs");
+    let result = multy(&["apply", "blur", "--radius", "9999", GRID, GRID, "-o", path(&outputs)]);
+    assert_eq!(result.status.code(), Some(2));
+    assert_eq!(String::from_utf8_lossy(&result.stderr).matches("error: ").count(), 1);
+    assert!(!outputs.exists());
+
+    let output = dir.path().join("never.png");
+    let result = multy(&["apply", "blur", "--then", "dilate:radius=0", GRID, "-o", path(&output)]);
+    assert_eq!(result.status.code(), Some(2));
+    assert!(!output.exists());
+
+    let result = multy(&["apply", "blur", "missing.png"]);
+    assert_eq!(result.status.code(), Some(2));
+}
+
+#[test]
+fn compare() {
+    let dir = tempfile::tempdir().unwrap();
+    let diff = dir.path().join("diff.png");
+
+    let result = multy(&["compare", GRID, GRID]);
+    assert_eq!(result.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&result.stdout).contains("identical"));
+
+    let result = multy(&["compare", "--json", GRID, NOISE, "-o", path(&diff)]);
+    assert_eq!(result.status.code(), Some(1));
+    assert!(diff.is_file());
+
+    let report: serde_json::Value = serde_json::from_slice(&result.stdout).unwrap();
+    let source = filter::open(Path::new(GRID)).unwrap();
+    assert_eq!(report["total_pixels"], source.width() as u64 * source.height() as u64);
+    assert!(report["different_pixels"].as_u64().unwrap() > 0);
+    assert!(report["ratio"].as_f64().unwrap() > 0.);
+}
+
+#[test]
+fn compare_directories() {
+    let dir = tempfile::tempdir().unwrap();
+    let left = dir.path().join("left");
+    let right = dir.path().join("right");
+    fs::create_dir(&left).unwrap();
+    fs::create_dir(&right).unwrap();
+    fs::copy(GRID, left.join("a.png")).unwrap();
+    fs::copy(GRID, right.join("a.png")).unwrap();
+    fs::copy(GRID, left.join("b.png")).unwrap();
+
+    // b.png is only on the left
+    let result = multy(&["compare", "--json", path(&left), path(&right)]);
+    assert_eq!(result.status.code(), Some(2));
+
+    let report: serde_json::Value = serde_json::from_slice(&result.stdout).unwrap();
+    let reports = report.as_array().unwrap();
+    assert_eq!(reports.len(), 2);
+    assert_eq!(reports[0]["different_pixels"], 0);
+    assert!(reports[1]["error"].is_string());
+}
@@ -0,0 +1,106 @@
+use crate::config::Config;
+use crate::file::FilterError;
+use crate::request::Upload;
+
+use std::collections::HashSet;
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+
+use rocket::serde::json::{serde_json, Value};
+use zip::{write::FileOptions, ZipArchive, ZipWriter};
+
+// the name of the images are only used to name their result
+pub struct BatchItem {
+    pub name: String,
+    pub upload: Result<Upload, FilterError>,
+}
+
+impl BatchItem {
+    pub fn new(index: usize, upload: Upload) -> Self {
+        Self { name: item_name(index, upload.file_name.as_deref()), upload: Ok(upload) }
+    }
+}
+
+// every file of a zip archive, at most `max_items` of them.
+// an entry above `max_upload_size` only fails itself
+pub fn read_archive(bytes: Vec<u8>, config: &Config, first: usize, max_items: usize) -> Result<Vec<BatchItem>, FilterError> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes))
+        .map_err(|e| FilterError::invalid_field("archive", e))?;
+    let limit = config.max_upload_size.as_u64();
+    let mut items = Vec::new();
+
+    for i in 0..archive.len() {
+        let file = archive.by_index(i).map_err(|e| FilterError::invalid_field("archive", e))?;
+        let file_name = file.enclosed_name()
+            .and_then(|path| path.file_name())
+            .and_then(|name| name.to_str())
+            .map(String::from);
+
+        // directories, and the metadata some archivers add
+        if file.is_dir() || file_name.as_deref().is_none_or(|name| name.starts_with('.')) {
+            continue;
+        }
+        if items.len() == max_items {
+            return Err(FilterError::invalid_field("archive", format!("a batch can't have more than {} images", max_items)));
+        }
+
+        let mut content = Vec::new();
+        let upload = match file.take(limit + 1).read_to_end(&mut content) {
+            Err(e) => Err(FilterError::invalid_field("archive", e)),
+            Ok(_) if content.len() as u64 > limit => Err(FilterError::TooLarge {
+                field: String::from("archive"),
+                limit: config.max_upload_size,
+            }),
+            Ok(_) => Ok(Upload { bytes: content, file_name: file_name.clone(), id: None }),
+        };
+
+        items.push(BatchItem { name: item_name(first + items.len(), file_name.as_deref()), upload });
+    }
+
+    Ok(items)
+}
+
+// ex: "photos/lena.jpg" becomes "lena.jpg", unnamed images become "image-3"
+fn item_name(index: usize, file_name: Option<&str>) -> String {
+    file_name
+        .and_then(|name| Path::new(name).file_name())
+        .and_then(|name| name.to_str())
+        .map(String::from)
+        .unwrap_or_else(|| format!("image-{}", index + 1))
+}
+
+// a file name for the result of `name`, not already in `taken`
+pub fn output_name(name: &str, extension: &str, taken: &mut HashSet<String>) -> String {
+    let stem = Path::new(name).file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or(name);
+
+    let mut output = format!("{}.{}", stem, extension);
+    let mut n = 1;
+    while taken.contains(&output) || output == MANIFEST {
+        n += 1;
+        output = format!("{}-{}.{}", stem, n, extension);
+    }
+
+    taken.insert(output.clone());
+    output
+}
+
+pub const MANIFEST: &str = "manifest.json";
+
+// the results, followed by the manifest
+pub fn write_archive(outputs: Vec<(String, Vec<u8>)>, manifest: &Value) -> Result<Vec<u8>, FilterError> {
+    let error = |e: &dyn std::fmt::Display| FilterError::Other(format!("Failed to write the archive: {}", e));
+    let mut archive = ZipWriter::new(Cursor::new(Vec::new()));
+
+    for (name, bytes) in outputs {
+        archive.start_file(name, FileOptions::default()).map_err(|e| error(&e))?;
+        archive.write_all(&bytes).map_err(|e| error(&e))?;
+    }
+
+    let manifest = serde_json::to_vec_pretty(manifest).map_err(|e| error(&e))?;
+    archive.start_file(MANIFEST, FileOptions::default()).map_err(|e| error(&e))?;
+    archive.write_all(&manifest).map_err(|e| error(&e))?;
+
+    Ok(archive.finish().map_err(|e| error(&e))?.into_inner())
+}
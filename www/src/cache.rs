@@ -0,0 +1,122 @@
+use crate::config::Config;
+use crate::request::Rect;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use filter::{Algorithms, EncodeOptions, Format};
+use sha2::{Digest, Sha256};
+
+// everything the result of `/apply` depends on
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    // sha256 of the content of the images
+    pub photo: String,
+    pub mask: Option<String>,
+    pub rect: Option<Rect>,
+    // the preset they come from doesn't matter
+    pub steps: Vec<Algorithms>,
+    pub format: Format,
+    pub options: EncodeOptions,
+}
+
+impl CacheKey {
+    pub fn hash(bytes: &[u8]) -> String {
+        format!("{:x}", Sha256::digest(bytes))
+    }
+}
+
+struct Entry {
+    bytes: Vec<u8>,
+    created_at: Instant,
+    // to evict the least recently used results first
+    used_at: u64,
+}
+
+#[derive(Default)]
+struct Entries {
+    entries: HashMap<CacheKey, Entry>,
+    // total size of the results
+    size: usize,
+    clock: u64,
+}
+
+// encoded results of `/apply`, evicted once older than `ttl`, or when they don't fit in `max_size` anymore.
+// clones share the same results.
+#[derive(Clone)]
+pub struct ResultCache {
+    max_size: usize,
+    ttl: Duration,
+    entries: Arc<Mutex<Entries>>,
+}
+
+impl ResultCache {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            max_size: config.cache_size.as_u64() as usize,
+            ttl: Duration::from_secs(config.cache_ttl),
+            entries: Arc::new(Mutex::new(Entries::default())),
+        }
+    }
+
+    // a cache of size 0 is never used
+    pub fn enabled(&self) -> bool {
+        self.max_size > 0
+    }
+
+    pub fn get(&self, key: &CacheKey) -> Option<Vec<u8>> {
+        if !self.enabled() {
+            return None;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let clock = entries.clock;
+
+        match entries.entries.get_mut(key) {
+            Some(entry) if entry.created_at.elapsed() < self.ttl => {
+                entry.used_at = clock;
+                Some(entry.bytes.clone())
+            },
+            Some(_) => {
+                let entry = entries.entries.remove(key).unwrap();
+                entries.size -= entry.bytes.len();
+                None
+            },
+            None => None,
+        }
+    }
+
+    // results larger than the whole cache are never kept
+    pub fn insert(&self, key: CacheKey, bytes: Vec<u8>) {
+        if !self.enabled() || bytes.len() > self.max_size {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        let ttl = self.ttl;
+        entries.entries.retain(|_, entry| entry.created_at.elapsed() < ttl);
+        entries.size = entries.entries.values().map(|entry| entry.bytes.len()).sum();
+        // its room is freed before making room for the new result
+        if let Some(previous) = entries.entries.remove(&key) {
+            entries.size -= previous.bytes.len();
+        }
+
+        while entries.size + bytes.len() > self.max_size {
+            let oldest = entries.entries.iter()
+                .min_by_key(|(_, entry)| entry.used_at)
+                .map(|(key, _)| key.clone());
+
+            match oldest.and_then(|key| entries.entries.remove(&key)) {
+                Some(entry) => entries.size -= entry.bytes.len(),
+                None => break,
+            }
+        }
+
+        entries.clock += 1;
+        entries.size += bytes.len();
+        let entry = Entry { bytes, created_at: Instant::now(), used_at: entries.clock };
+        entries.entries.insert(key, entry);
+    }
+}
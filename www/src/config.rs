@@ -0,0 +1,116 @@
+use crate::file::FilterError;
+use crate::storage::S3Config;
+
+use filter::{AlgorithmInfo, Algorithms};
+use rocket::data::ByteUnit;
+use rocket::figment::value::magic::RelativePathBuf;
+use rocket::serde::Deserialize;
+
+// read from `Rocket.toml` or the `ROCKET_` environment variables, next to rocket's own settings
+#[derive(Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct Config {
+    pub storage: StorageKind,
+    // only for the "s3" storage
+    pub s3: Option<S3Config>,
+    // relative paths set in `Rocket.toml` are relative to it, otherwise to the working directory
+    pub storage_root: RelativePathBuf,
+    pub style_dir: RelativePathBuf,
+    // size of every uploaded image
+    pub max_upload_size: ByteUnit,
+    pub max_width: u32,
+    pub max_height: u32,
+    pub max_pixels: u64,
+    // total size of the cached results of `/apply`, 0 to disable the cache
+    pub cache_size: ByteUnit,
+    // in seconds
+    pub cache_ttl: u64,
+    // threads running the jobs
+    pub workers: usize,
+    // jobs waiting for a worker, more are refused
+    pub job_queue_size: usize,
+    // images of a single `/batch`
+    pub max_batch_size: usize,
+    // in seconds, how long a derived image is kept once unused, 0 to keep them
+    pub derived_ttl: u64,
+    // total size of the stored images, the least recently used ones are deleted above it. 0 for no limit
+    pub storage_quota: ByteUnit,
+    // in seconds, between two sweeps of the stored images
+    pub reaper_interval: u64,
+    // names of the algorithms served, all of them when missing
+    pub algorithms: Option<Vec<String>>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum StorageKind {
+    // files under `storage_root`
+    Fs,
+    Memory,
+    S3,
+}
+
+impl Config {
+    pub fn limits(&self) -> filter::Limits {
+        filter::Limits {
+            max_width: self.max_width,
+            max_height: self.max_height,
+            max_pixels: self.max_pixels,
+        }
+    }
+
+    // checked when the server starts, so a typo doesn't silently disable an algorithm
+    pub fn check(&self) -> Result<(), String> {
+        for name in self.algorithms.iter().flatten() {
+            if !filter::registry().iter().any(|info| info.name == name) {
+                return Err(format!("\"{}\" in `algorithms` isn't a valid algorithm name", name));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn allowed_algorithms(&self) -> Vec<&'static AlgorithmInfo> {
+        filter::registry().iter()
+            .filter(|info| self.is_allowed(info.name))
+            .collect()
+    }
+
+    // the algorithms left out are unknown to the clients
+    pub fn allow(&self, algo: &Algorithms) -> Result<(), FilterError> {
+        match self.is_allowed(algo.info().name) {
+            true => Ok(()),
+            false => Err(FilterError::Image(filter::Error::UnknownAlgorithm(algo.info().name.to_owned()))),
+        }
+    }
+
+    fn is_allowed(&self, name: &str) -> bool {
+        self.algorithms.as_ref().is_none_or(|algorithms| algorithms.iter().any(|algorithm| algorithm == name))
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let limits = filter::Limits::default();
+
+        Self {
+            storage: StorageKind::Fs,
+            s3: None,
+            storage_root: RelativePathBuf::from("static/images"),
+            style_dir: RelativePathBuf::from("static/style"),
+            max_upload_size: ByteUnit::Mebibyte(8),
+            max_width: limits.max_width,
+            max_height: limits.max_height,
+            max_pixels: limits.max_pixels,
+            cache_size: ByteUnit::Mebibyte(64),
+            cache_ttl: 600,
+            workers: 2,
+            job_queue_size: 64,
+            max_batch_size: 100,
+            derived_ttl: 24 * 60 * 60,
+            storage_quota: ByteUnit::Byte(0),
+            reaper_interval: 5 * 60,
+            algorithms: None,
+        }
+    }
+}
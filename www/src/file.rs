@@ -1,41 +1,155 @@
-use std::{fmt::Display, error::Error, ffi::OsStr, path::Path, path::PathBuf};
-use filter::ImageError;
+use std::{fmt::Display, error::Error};
+use rocket::{
+    data::ByteUnit, http::Status, request::Request, response::{self, status, Responder}, serde::json::{json, Json, Value},
+};
 
 #[derive(Debug)]
 pub enum FilterError {
-    DestImg(String),
-    Image(String),
+    MissingField(String),
+    InvalidField { field: String, reason: String },
+    MalformedBody(String),
+    ContentType(String),
+    Image(filter::Error),
+    UnknownImage { field: String, id: String },
+    TooLarge { field: String, limit: ByteUnit },
+    UnknownJob(String),
+    UnknownPreset(String),
+    PresetExists(String),
+    QueueFull,
     Other(String),
 }
 
 impl FilterError {
+    pub fn invalid_field(field: &str, reason: impl ToString) -> Self {
+        FilterError::InvalidField { field: String::from(field), reason: reason.to_string() }
+    }
+
     pub fn get_error_string(self) -> String {
         match self {
-            FilterError::DestImg(s) |
-            FilterError::Image(s) |
+            FilterError::MissingField(field) => format!("Missing \"{}\" field.", field),
+            FilterError::InvalidField { field, reason } => format!("Invalid \"{}\": {}", field, reason),
+            FilterError::UnknownImage { id, .. } => format!("There's no image \"{}\".", id),
+            FilterError::TooLarge { field, limit } => format!("\"{}\" is larger than {}.", field, limit),
+            FilterError::UnknownJob(id) => format!("There's no job \"{}\".", id),
+            FilterError::UnknownPreset(name) => format!("There's no preset \"{}\".", name),
+            FilterError::PresetExists(name) => format!("There's already a preset \"{}\".", name),
+            FilterError::QueueFull => String::from("Too many jobs are waiting, try again later."),
+            FilterError::MalformedBody(s) |
+            FilterError::ContentType(s) |
             FilterError::Other(s) => s,
+            FilterError::Image(e) => e.to_string(),
+        }
+    }
+
+    pub fn status(&self) -> Status {
+        match self {
+            FilterError::MissingField(_) |
+            FilterError::InvalidField { .. } |
+            FilterError::MalformedBody(_) => Status::BadRequest,
+            FilterError::ContentType(_) => Status::UnsupportedMediaType,
+            FilterError::Image(e) => match e {
+                filter::Error::Decode(_) => Status::BadRequest,
+                filter::Error::InvalidParameter { .. } |
+                filter::Error::UnknownAlgorithm(_) |
+                filter::Error::DimensionMismatch { .. } => Status::UnprocessableEntity,
+                filter::Error::UnsupportedFormat(_) => Status::UnsupportedMediaType,
+                filter::Error::TooLarge(_) => Status::PayloadTooLarge,
+                filter::Error::Cancelled => Status::ServiceUnavailable,
+                filter::Error::Encode(_) |
+                filter::Error::Io(_) => Status::InternalServerError,
+            },
+            FilterError::UnknownImage { .. } |
+            FilterError::UnknownJob(_) |
+            FilterError::UnknownPreset(_) => Status::NotFound,
+            FilterError::PresetExists(_) => Status::Conflict,
+            FilterError::TooLarge { .. } => Status::PayloadTooLarge,
+            FilterError::QueueFull => Status::ServiceUnavailable,
+            FilterError::Other(_) => Status::InternalServerError,
+        }
+    }
+
+    // stable, machine readable, unlike the message
+    pub fn code(&self) -> &'static str {
+        match self {
+            FilterError::MissingField(_) => "missing_field",
+            FilterError::InvalidField { .. } => "invalid_field",
+            FilterError::MalformedBody(_) => "malformed_body",
+            FilterError::ContentType(_) => "unsupported_content_type",
+            FilterError::Image(e) => match e {
+                filter::Error::Decode(_) => "decode_failed",
+                filter::Error::Encode(_) => "encode_failed",
+                filter::Error::InvalidParameter { .. } => "invalid_parameter",
+                filter::Error::UnknownAlgorithm(_) => "unknown_algorithm",
+                filter::Error::DimensionMismatch { .. } => "size_mismatch",
+                filter::Error::UnsupportedFormat(_) => "unsupported_format",
+                filter::Error::TooLarge(_) => "image_too_large",
+                filter::Error::Cancelled => "cancelled",
+                filter::Error::Io(_) => "internal_error",
+            },
+            FilterError::UnknownImage { .. } => "unknown_image",
+            FilterError::TooLarge { .. } => "payload_too_large",
+            FilterError::UnknownJob(_) => "unknown_job",
+            FilterError::UnknownPreset(_) => "unknown_preset",
+            FilterError::PresetExists(_) => "preset_exists",
+            FilterError::QueueFull => "queue_full",
+            FilterError::Other(_) => "internal_error",
+        }
+    }
+
+    pub fn field(&self) -> Option<&str> {
+        match self {
+            FilterError::MissingField(field) |
+            FilterError::InvalidField { field, .. } |
+            FilterError::UnknownImage { field, .. } |
+            FilterError::TooLarge { field, .. } => Some(field),
+            FilterError::Image(filter::Error::InvalidParameter { parameter, .. }) => Some(parameter),
+            FilterError::Image(filter::Error::UnknownAlgorithm(_)) => Some("algorithm"),
+            FilterError::UnknownJob(_) => Some("id"),
+            FilterError::UnknownPreset(_) => Some("preset"),
+            FilterError::PresetExists(_) => Some("name"),
+            _ => None,
+        }
+    }
+
+    pub fn details(&self) -> Option<Value> {
+        match self {
+            FilterError::TooLarge { limit, .. } => Some(json!({ "limit": limit.as_u64() })),
+            FilterError::Image(filter::Error::DimensionMismatch { expected, found }) => Some(json!({
+                "expected": { "width": expected.0, "height": expected.1 },
+                "found": { "width": found.0, "height": found.1 },
+            })),
+            _ => None,
         }
     }
 
+    // the body of the error responses
+    pub fn into_json(self) -> Value {
+        json!({
+            "code": self.code(),
+            "field": self.field(),
+            "details": self.details(),
+            "message": self.get_error_string(),
+        })
+    }
+
     // pub fn get_ref_error_string(&self) -> &'_ str {
     //     match self {
-    //         FilterError::DestImg(s) |
     //         FilterError::Image(s) |
     //         FilterError::Other(s) => s,
     //     }
     // }
 }
 
-impl From<ImageError> for FilterError {
-    fn from(err: ImageError) -> Self {
-        match err {
-            ImageError::Decoding(e) => FilterError::Image(e.to_string()),
-            ImageError::Encoding(e) => FilterError::Image(e.to_string()),
-            ImageError::Parameter(e) => FilterError::Image(e.to_string()),
-            ImageError::Limits(e) => FilterError::Image(e.to_string()),
-            ImageError::Unsupported(e) => FilterError::Image(e.to_string()),
-            ImageError::IoError(e) => FilterError::Image(e.to_string()),
-        }
+impl From<filter::Error> for FilterError {
+    fn from(err: filter::Error) -> Self {
+        FilterError::Image(err)
+    }
+}
+
+impl<'r> Responder<'r, 'static> for FilterError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let status = self.status();
+        status::Custom(status, Json(self.into_json())).respond_to(request)
     }
 }
 
@@ -46,40 +160,3 @@ impl Display for FilterError {
 }
 
 impl Error for FilterError {}
-
-pub fn orig_filename_extension(path: &Path) -> Result<(&OsStr, &OsStr), FilterError> {
-    let file_stem = path.file_stem();
-    let extension = path.extension();
-
-    match (file_stem, extension) {
-        (Some(file_stem), Some(extension)) => Ok((file_stem, extension)),
-        (None, Some(_)) => Err(FilterError::DestImg(format!("Path: {:?}, doesn't have a filename", path))),
-        (Some(_), None) => Err(FilterError::DestImg(format!("Path: {:?}, doesn't have an extension", path))),
-        (None, None) => Err(FilterError::DestImg(format!("Path: {:?}, doesn't have any filename or extension", path))),
-    }
-}
-
-pub fn get_new_image_file(path: &Path, file_name_add: &str) -> Result<PathBuf, FilterError> {
-    let (file_stem, extension) = orig_filename_extension(path)?;
-
-    // prevent string realloc
-    let mut new_path = String::with_capacity(file_stem.len() + file_name_add.len() + extension.len() + 2);
-
-    new_path.push_str(file_stem.to_str().ok_or_else(||
-        FilterError::Other(String::from("Failed to extract str from file_stem"))
-    )?);
-    new_path.push('_');
-    new_path.push_str(file_name_add);
-    new_path.push('.');
-    new_path.push_str(extension.to_str().ok_or_else(||
-        FilterError::Other(String::from("Failed to extract str from extension"))
-    )?);
-
-    let base_path = "static/images";
-    let mut to_save = PathBuf::with_capacity(base_path.len() + new_path.len());
-
-    to_save.push(base_path);
-    to_save.push(new_path);
-
-    Ok(to_save)
-}
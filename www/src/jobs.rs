@@ -0,0 +1,208 @@
+use crate::file::FilterError;
+use crate::store::now;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::thread;
+
+use filter::{CancelToken, ProcessContext};
+use rand::{distributions::Alphanumeric, Rng};
+use rocket::serde::{Serialize, json::Value};
+
+// finished jobs are forgotten after an hour
+const FINISHED_JOB_TTL: u64 = 60 * 60;
+
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Job {
+    pub id: String,
+    pub status: JobStatus,
+    // between 0 and 1
+    pub progress: f32,
+    // seconds since the unix epoch
+    pub created_at: u64,
+    pub finished_at: Option<u64>,
+    // url of the result once done
+    pub result: Option<String>,
+    // same body as the error responses, once failed
+    pub error: Option<Value>,
+    #[serde(skip)]
+    cancel: CancelToken,
+}
+
+impl Job {
+    fn finished(&self) -> bool {
+        self.finished_at.is_some()
+    }
+}
+
+// computes the url of the result, following the context
+pub type Task = Box<dyn FnOnce(&ProcessContext) -> Result<String, FilterError> + Send>;
+
+type Jobs = Arc<Mutex<HashMap<String, Job>>>;
+
+// tasks are run one at a time by each of the `workers` threads, in the order they were submitted
+pub struct JobQueue {
+    jobs: Jobs,
+    sender: SyncSender<(String, Task)>,
+}
+
+impl JobQueue {
+    pub fn new(workers: usize, queue_size: usize) -> Self {
+        let jobs = Jobs::default();
+        let (sender, receiver) = mpsc::sync_channel(queue_size);
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        for _ in 0..workers.max(1) {
+            let jobs = jobs.clone();
+            let receiver = receiver.clone();
+            thread::spawn(move || work(jobs, receiver));
+        }
+
+        Self { jobs, sender }
+    }
+
+    pub fn submit(&self, task: Task) -> Result<Job, FilterError> {
+        let id = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(16)
+            .map(char::from)
+            .collect::<String>();
+        let job = Job {
+            id: id.clone(),
+            status: JobStatus::Queued,
+            progress: 0.0,
+            created_at: now(),
+            finished_at: None,
+            result: None,
+            error: None,
+            cancel: CancelToken::default(),
+        };
+
+        {
+            let mut jobs = self.jobs.lock().unwrap();
+            let expired = now().saturating_sub(FINISHED_JOB_TTL);
+            jobs.retain(|_, job| job.finished_at.is_none_or(|finished_at| finished_at > expired));
+            jobs.insert(id.clone(), job.clone());
+        }
+
+        match self.sender.try_send((id.clone(), task)) {
+            Ok(()) => Ok(job),
+            Err(e) => {
+                self.jobs.lock().unwrap().remove(&id);
+                match e {
+                    TrySendError::Full(_) => Err(FilterError::QueueFull),
+                    TrySendError::Disconnected(_) => Err(FilterError::Other(String::from("The workers are gone."))),
+                }
+            },
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<Job> {
+        self.jobs.lock().unwrap().get(id).cloned()
+    }
+
+    // unfinished jobs are cancelled, finished ones forgotten.
+    // returns false when there's no such job
+    pub fn cancel(&self, id: &str) -> bool {
+        let mut jobs = self.jobs.lock().unwrap();
+        let finished = match jobs.get_mut(id) {
+            Some(job) if job.finished() => true,
+            Some(job) => {
+                job.cancel.cancel();
+                job.status = JobStatus::Cancelled;
+                job.finished_at = Some(now());
+                false
+            },
+            None => return false,
+        };
+
+        if finished {
+            jobs.remove(id);
+        }
+        true
+    }
+}
+
+fn work(jobs: Jobs, receiver: Arc<Mutex<Receiver<(String, Task)>>>) {
+    loop {
+        // the queue is gone with its sender
+        let (id, task) = match receiver.lock().unwrap().recv() {
+            Ok(next) => next,
+            Err(_) => return,
+        };
+
+        let cancel = match jobs.lock().unwrap().get_mut(&id) {
+            Some(job) if job.status == JobStatus::Queued => {
+                job.status = JobStatus::Running;
+                job.cancel.clone()
+            },
+            _ => continue,
+        };
+
+        let progress = {
+            let (jobs, id) = (jobs.clone(), id.clone());
+            move |done: u32, total: u32| {
+                if let Some(job) = jobs.lock().unwrap().get_mut(&id) {
+                    job.progress = done as f32 / total as f32;
+                }
+            }
+        };
+        let ctx = ProcessContext { progress: Some(Arc::new(progress)), cancel: Some(cancel) };
+
+        let _crashed = Crashed { jobs: &jobs, id: &id };
+        let result = task(&ctx);
+
+        let mut jobs = jobs.lock().unwrap();
+        let job = match jobs.get_mut(&id) {
+            Some(job) if job.status == JobStatus::Running => job,
+            _ => continue,
+        };
+        match result {
+            Ok(url) => {
+                job.status = JobStatus::Done;
+                job.progress = 1.0;
+                job.result = Some(url);
+            },
+            Err(e) => {
+                job.status = JobStatus::Failed;
+                job.error = Some(e.into_json());
+            },
+        }
+        job.finished_at = Some(now());
+    }
+}
+
+// a panic is a bug of the filters, it isn't caught and ends its worker.
+// the job still fails instead of staying running forever
+struct Crashed<'a> {
+    jobs: &'a Jobs,
+    id: &'a str,
+}
+
+impl Drop for Crashed<'_> {
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            return;
+        }
+
+        if let Ok(mut jobs) = self.jobs.lock() {
+            if let Some(job) = jobs.get_mut(self.id).filter(|job| job.status == JobStatus::Running) {
+                job.status = JobStatus::Failed;
+                job.error = Some(FilterError::Other(String::from("The job crashed.")).into_json());
+                job.finished_at = Some(now());
+            }
+        }
+    }
+}
@@ -1,131 +1,723 @@
-#![feature(proc_macro_hygiene, decl_macro)]
-
 mod utils;
+mod batch;
+mod cache;
+mod jobs;
+mod presets;
+mod config;
 mod file;
+mod request;
+mod response;
+mod retention;
+mod storage;
+mod store;
+#[cfg(test)]
+mod tests;
 
 #[macro_use]
 extern crate rocket;
 
+use rocket::{Build, Request, Rocket, State};
 use rocket::data::Data;
+use rocket::fairing::AdHoc;
 use rocket::response::status;
-use rocket::http::ContentType;
-use rocket::fs::{FileServer, NamedFile};
+use rocket::http::{Accept, ContentType, Status};
+use rocket::fs::FileServer;
+use rocket::serde::json::{json, Json, Value};
 
 use rocket_dyn_templates::Template;
 
+use batch::BatchItem;
+use cache::{CacheKey, ResultCache};
+use config::Config;
+use jobs::{Job, JobQueue};
+use file::FilterError;
+use request::{Apply, ApplyRequest, CompareRequest, ImageSource, OutputQuery, Upload};
+use rocket_multipart_form_data::MultipartFormData;
+use response::{ArchiveResponse, ImageResponse, JobCreated};
+use presets::{Operation, Preset, PresetRequest, PresetStore};
+use store::{ImageEntry, ImageStore, Lineage};
+
 
 #[get("/")]
 fn index() -> &'static str {
     "Hello, world!"
 }
 
+#[get("/algorithms")]
+fn algorithms(config: &State<Config>) -> Json<Vec<&'static filter::AlgorithmInfo>> {
+    Json(config.allowed_algorithms())
+}
+
 #[post("/save", data = "<data>")]
-async fn save(content_type: &ContentType, data: Data<'_>) -> Result<status::Created<String>, status::BadRequest<String>> {
+async fn save(content_type: Option<&ContentType>, config: &State<Config>, store: &State<ImageStore>, data: Data<'_>) -> Result<status::Created<String>, FilterError> {
+    let fields = vec![
+        utils::AllowedField::Raw("photo"),
+    ];
+
+    let mut multipart_form_data = utils::get_multipart_form_data(content_type, data, fields, config).await?;
+    let photo = utils::get_raw(&mut multipart_form_data, "photo")?;
+    let store = store.inner().clone();
+    let id = blocking(move || store.put(&photo.raw, photo.file_name)).await?;
+
+    Ok(status::Created::new(format!("/public/{}", id)))
+}
+
+#[post("/apply", data = "<data>", rank = 2)]
+async fn apply(content_type: Option<&ContentType>, accept: Option<&Accept>, config: &State<Config>, store: &State<ImageStore>, cache: &State<ResultCache>, data: Data<'_>) -> Result<ImageResponse, FilterError> {
     let fields = vec![
-        utils::AllowedField::File("photo"),
+        utils::AllowedField::Text("preset"),
+        utils::AllowedField::Text("steps"),
+        utils::AllowedField::Text("algorithm"),
+        utils::AllowedField::Text("radius"),
+        utils::AllowedField::Text("factor"),
+        utils::AllowedField::Text("roi"),
+        utils::AllowedField::Text("persist"),
+        utils::AllowedField::Text("format"),
+        utils::AllowedField::Text("quality"),
+        utils::AllowedField::Text("compression"),
+        utils::AllowedField::Raw("photo"),
+        utils::AllowedField::Raw("mask"),
     ];
 
-    let mut multipart_form_data = utils::get_multipart_form_data(content_type, data, fields).await;
-    let location = utils::save_image(&mut multipart_form_data, "photo").map_err(|e|
-        status::BadRequest(Some(e))
-    )?;
+    let mut multipart_form_data = utils::get_multipart_form_data(content_type, data, fields, config).await?;
+    let photo = Upload::from(utils::get_raw(&mut multipart_form_data, "photo")?);
 
-    let url = "/public/";
-    let file = location
-        .file_name().unwrap()
-        .to_str().unwrap();
+    let (config, store, cache, accept) = (config.inner().clone(), store.inner().clone(), cache.inner().clone(), accept.cloned());
+    blocking(move || {
+        let apply = read_form(&mut multipart_form_data, photo, false, accept.as_ref(), &config, &store.presets())?;
+        apply_upload(&config, &store, &cache, apply, &filter::ProcessContext::default())
+    }).await
+}
 
-    let mut location = String::with_capacity(url.len() + file.len());
-    location.push_str(url);
-    location.push_str(file);
+#[post("/apply", format = "json", data = "<data>", rank = 1)]
+async fn apply_json(accept: Option<&Accept>, config: &State<Config>, store: &State<ImageStore>, cache: &State<ResultCache>, data: Data<'_>) -> Result<ImageResponse, FilterError> {
+    let mut request: ApplyRequest = request::get_json(data, config, 2).await?;
 
-    Ok(status::Created::new(location))
+    let (config, store, cache, accept) = (config.inner().clone(), store.inner().clone(), cache.inner().clone(), accept.cloned());
+    blocking(move || {
+        let photo = request::get_upload(&store, &config, "photo", request.photo.take())?;
+        let apply = read_request(request, photo, false, accept.as_ref(), &config, &store)?;
+        apply_upload(&config, &store, &cache, apply, &filter::ProcessContext::default())
+    }).await
 }
 
-#[post("/apply", data = "<data>")]
-async fn apply(content_type: &ContentType, data: Data<'_>) -> Result<NamedFile, status::BadRequest<String>> {
+#[post("/images/<id>/apply", data = "<data>", rank = 2)]
+async fn apply_stored(id: &str, content_type: Option<&ContentType>, accept: Option<&Accept>, config: &State<Config>, store: &State<ImageStore>, cache: &State<ResultCache>, data: Data<'_>) -> Result<ImageResponse, FilterError> {
+    let photo = get_stored(config, store, id).await?;
+
     let fields = vec![
+        utils::AllowedField::Text("preset"),
+        utils::AllowedField::Text("steps"),
         utils::AllowedField::Text("algorithm"),
         utils::AllowedField::Text("radius"),
         utils::AllowedField::Text("factor"),
-        utils::AllowedField::File("photo"),
+        utils::AllowedField::Text("roi"),
+        utils::AllowedField::Text("format"),
+        utils::AllowedField::Text("quality"),
+        utils::AllowedField::Text("compression"),
+        utils::AllowedField::Raw("mask"),
     ];
 
-    let mut multipart_form_data = utils::get_multipart_form_data(content_type, data, fields).await;
-    let source = utils::save_image(&mut multipart_form_data, "photo").map_err(|e|
-        status::BadRequest(Some(e))
-    )?;
-    let (algo, name) = utils::get_algo(&mut multipart_form_data).map_err(|e|
-        status::BadRequest(Some(e))
-    )?;
+    let mut multipart_form_data = utils::get_multipart_form_data(content_type, data, fields, config).await?;
 
-    let dest = file::get_new_image_file(source.as_path(), &name)
-        .map_err(|e| status::BadRequest(Some(e.get_error_string())))?;
+    let (config, store, cache, accept) = (config.inner().clone(), store.inner().clone(), cache.inner().clone(), accept.cloned());
+    blocking(move || {
+        let apply = read_form(&mut multipart_form_data, photo, true, accept.as_ref(), &config, &store.presets())?;
+        apply_upload(&config, &store, &cache, apply, &filter::ProcessContext::default())
+    }).await
+}
+
+#[post("/images/<id>/apply", format = "json", data = "<data>", rank = 1)]
+async fn apply_stored_json(id: &str, accept: Option<&Accept>, config: &State<Config>, store: &State<ImageStore>, cache: &State<ResultCache>, data: Data<'_>) -> Result<ImageResponse, FilterError> {
+    let photo = get_stored(config, store, id).await?;
 
-    filter::run_algo(&source, &dest, algo).map_err(|e|
-        status::BadRequest(Some(e.to_string()))
-    )?;
+    let request: ApplyRequest = request::get_json(data, config, 1).await?;
+    if request.photo.is_some() {
+        return Err(FilterError::invalid_field("photo", "the image is already given by the url"));
+    }
+
+    let (config, store, cache, accept) = (config.inner().clone(), store.inner().clone(), cache.inner().clone(), accept.cloned());
+    blocking(move || {
+        let apply = read_request(request, photo, true, accept.as_ref(), &config, &store)?;
+        apply_upload(&config, &store, &cache, apply, &filter::ProcessContext::default())
+    }).await
+}
 
-    NamedFile::open(&dest).await.map_err(|e|
-        status::BadRequest(Some(e.to_string()))
-    )
+async fn get_stored(config: &Config, store: &ImageStore, id: &str) -> Result<Upload, FilterError> {
+    let (config, store, id) = (config.clone(), store.clone(), id.to_owned());
+    blocking(move || request::get_upload(&store, &config, "id", Some(ImageSource::Id(id)))).await
 }
 
-#[post("/compare", data = "<data>")]
-async fn compare(content_type: &ContentType, data: Data<'_>) -> Result<NamedFile, status::BadRequest<String>> {
+// stored images always keep their results, so edits can be chained
+fn read_form(multipart_form_data: &mut MultipartFormData, photo: Upload, stored: bool, accept: Option<&Accept>, config: &Config, presets: &PresetStore) -> Result<Apply, FilterError> {
+    let operation = utils::get_operation(multipart_form_data, config, presets)?;
+    let (rect, mask) = utils::get_roi(multipart_form_data)?;
+    let persist = stored || utils::get_persist(multipart_form_data)?;
+    // without any requested format, keep the format of the upload when we can write it back
+    let fallback = filter::Format::guess(&photo.bytes).unwrap_or(filter::Format::Png);
+    let (format, options) = utils::get_output(multipart_form_data, accept, fallback)?;
+
+    Ok(Apply { photo, operation, rect, mask, persist, format, options })
+}
+
+fn read_request(request: ApplyRequest, photo: Upload, stored: bool, accept: Option<&Accept>, config: &Config, store: &ImageStore) -> Result<Apply, FilterError> {
+    let given = [("algorithm", request.algorithm.is_some()), ("radius", request.radius.is_some()), ("factor", request.factor.is_some())];
+    let exclusive = |with: &str| match given.iter().find(|(_, given)| *given) {
+        Some((field, _)) => Err(FilterError::invalid_field(field, format!("can't be used with \"{}\"", with))),
+        None => Ok(()),
+    };
+    let operation = match (request.preset, request.steps, request.algorithm) {
+        (Some(_), Some(_), _) => return Err(FilterError::invalid_field("steps", "can't be used with \"preset\"")),
+        (Some(preset), None, _) => {
+            exclusive("preset")?;
+            store.presets().operation(&preset, config)?
+        },
+        (None, Some(steps), _) => {
+            exclusive("steps")?;
+            Operation::inline(&steps, config)?
+        },
+        (None, None, Some(algorithm)) => {
+            let algo = filter::Algorithms::try_from(algorithm.as_str())?;
+            config.allow(&algo)?;
+            Operation::single(utils::with_parameters(algo, request.radius, request.factor)?)
+        },
+        (None, None, None) => return Err(FilterError::MissingField(String::from("algorithm"))),
+    };
+
+    let mask = match request.mask {
+        Some(mask) => Some(request::get_upload(store, config, "mask", Some(mask))?),
+        None => None,
+    };
+    let (rect, mask) = utils::check_roi(request.roi, mask)?;
+
+    let fallback = filter::Format::guess(&photo.bytes).unwrap_or(filter::Format::Png);
+    let (format, options) = utils::parse_output(request.format.as_deref(), request.quality, request.compression.as_deref(), accept, fallback)?;
+    let persist = stored || request.persist;
+
+    Ok(Apply { photo, operation, rect, mask, persist, format, options })
+}
+
+fn apply_upload(config: &Config, store: &ImageStore, cache: &ResultCache, apply: Apply, ctx: &filter::ProcessContext) -> Result<ImageResponse, FilterError> {
+    let Apply { photo, operation, rect, mask, persist, format, options } = apply;
+
+    let key = CacheKey {
+        photo: CacheKey::hash(&photo.bytes),
+        mask: mask.as_ref().map(|mask| CacheKey::hash(&mask.bytes)),
+        steps: operation.steps.clone(),
+        rect, format, options,
+    };
+    let hit = cache.get(&key);
+    let cached = cache.enabled().then(|| hit.is_some());
+
+    let bytes = match hit {
+        Some(bytes) => bytes,
+        None => {
+            let roi = match (rect, &mask) {
+                (Some(rect), _) => Some(filter::Roi::from(rect)),
+                (None, Some(mask)) => Some(filter::Roi::from_mask_bytes(&mask.bytes, &config.limits())?),
+                (None, None) => None,
+            };
+
+            let source = filter::decode_with(&photo.bytes, &config.limits())?;
+            let buffer = filter::process_pipeline(&source, &operation.steps, roi.as_ref(), ctx)?;
+            let bytes = filter::encode_with(&buffer, format, &options)?;
+
+            cache.insert(key, bytes.clone());
+            bytes
+        },
+    };
+
+    if !persist {
+        return Ok(ImageResponse { bytes, format, location: None, cached });
+    }
+
+    let mut sources = vec![photo.store(store)?];
+    let mask = match mask {
+        Some(mask) => {
+            let id = mask.store(store)?;
+            sources.push(id.clone());
+            Some(id)
+        },
+        None => None,
+    };
+
+    let mut parameters = operation.describe();
+    parameters["roi"] = json!(rect);
+    parameters["mask"] = json!(mask);
+    parameters["format"] = json!(format.extension());
+    parameters["quality"] = json!(options.quality);
+    parameters["compression"] = json!(options.compression.to_string());
+    let lineage = Lineage::new("apply", parameters);
+
+    let location = Some(format!("/public/{}", store.put_derived(&bytes, sources, lineage)?));
+    Ok(ImageResponse { bytes, format, location, cached })
+}
+
+// one algorithm or pipeline on every image, either sent as `photo` fields or in an `archive`.
+// the images failing don't fail the batch, they're reported in the manifest
+#[post("/batch", data = "<data>")]
+async fn apply_batch(content_type: Option<&ContentType>, accept: Option<&Accept>, config: &State<Config>, store: &State<ImageStore>, cache: &State<ResultCache>, data: Data<'_>) -> Result<ArchiveResponse, FilterError> {
+    // one more than allowed, to tell when there are too many
+    let max_photos = config.max_batch_size as u32 + 1;
+    let fields = vec![
+        utils::AllowedField::Text("preset"),
+        utils::AllowedField::Text("steps"),
+        utils::AllowedField::Text("algorithm"),
+        utils::AllowedField::Text("radius"),
+        utils::AllowedField::Text("factor"),
+        utils::AllowedField::Text("roi"),
+        utils::AllowedField::Text("format"),
+        utils::AllowedField::Text("quality"),
+        utils::AllowedField::Text("compression"),
+        utils::AllowedField::Raws("photo", max_photos),
+        utils::AllowedField::Archive("archive"),
+    ];
+
+    let mut multipart_form_data = utils::get_multipart_form_data(content_type, data, fields, config).await?;
+
+    // the whole batch runs on a blocking thread, not to hold a worker of rocket
+    let (config, store, cache, accept) = (config.inner().clone(), store.inner().clone(), cache.inner().clone(), accept.cloned());
+    let archive = blocking(move || {
+        let operation = utils::get_operation(&mut multipart_form_data, &config, &store.presets())?;
+        let (rect, _) = utils::get_roi(&mut multipart_form_data)?;
+        let output = utils::get_output_query(&mut multipart_form_data)?;
+        // checked once for all the images
+        utils::parse_output(output.format.as_deref(), output.quality, output.compression.as_deref(), accept.as_ref(), filter::Format::Png)?;
+
+        let mut items = multipart_form_data.raw.remove("photo").unwrap_or_default().into_iter()
+            .enumerate()
+            .map(|(index, field)| BatchItem::new(index, Upload::from(field)))
+            .collect::<Vec<_>>();
+        if items.len() > config.max_batch_size {
+            return Err(FilterError::invalid_field("photo", format!("a batch can't have more than {} images", config.max_batch_size)));
+        }
+        if multipart_form_data.raw.contains_key("archive") {
+            let archive = utils::get_raw(&mut multipart_form_data, "archive")?;
+            items.extend(batch::read_archive(archive.raw, &config, items.len(), config.max_batch_size - items.len())?);
+        }
+        if items.is_empty() {
+            return Err(FilterError::MissingField(String::from("photo")));
+        }
+
+        let mut taken = std::collections::HashSet::new();
+        let mut outputs = Vec::new();
+        let mut images = Vec::new();
+        for item in items {
+            let result = item.upload.and_then(|photo| {
+                let fallback = filter::Format::guess(&photo.bytes).unwrap_or(filter::Format::Png);
+                let (format, options) = utils::parse_output(output.format.as_deref(), output.quality, output.compression.as_deref(), accept.as_ref(), fallback)?;
+                let apply = Apply { photo, operation: operation.clone(), rect, mask: None, persist: false, format, options };
+                apply_upload(&config, &store, &cache, apply, &filter::ProcessContext::default())
+            });
+
+            match result {
+                Ok(response) => {
+                    let name = batch::output_name(&item.name, response.format.extension(), &mut taken);
+                    images.push(json!({ "name": item.name, "output": name, "error": null }));
+                    outputs.push((name, response.bytes));
+                },
+                Err(e) => images.push(json!({ "name": item.name, "output": null, "error": e.into_json() })),
+            }
+        }
+
+        let succeeded = outputs.len();
+        let mut manifest = operation.describe();
+        manifest["roi"] = json!(rect);
+        manifest["images"] = json!(images);
+        manifest["succeeded"] = json!(succeeded);
+        manifest["failed"] = json!(images.len() - succeeded);
+
+        batch::write_archive(outputs, &manifest)
+    }).await?;
+
+    Ok(ArchiveResponse::new(archive, "batch.zip"))
+}
+
+// same parameters as `/apply`, run in the background. the result is always stored
+#[post("/jobs", data = "<data>", rank = 2)]
+async fn submit_job(content_type: Option<&ContentType>, accept: Option<&Accept>, config: &State<Config>, store: &State<ImageStore>, cache: &State<ResultCache>, jobs: &State<JobQueue>, data: Data<'_>) -> Result<JobCreated, FilterError> {
     let fields = vec![
-        utils::AllowedField::File("left"),
-        utils::AllowedField::File("right"),
+        utils::AllowedField::Text("preset"),
+        utils::AllowedField::Text("steps"),
+        utils::AllowedField::Text("algorithm"),
+        utils::AllowedField::Text("radius"),
+        utils::AllowedField::Text("factor"),
+        utils::AllowedField::Text("roi"),
+        utils::AllowedField::Text("format"),
+        utils::AllowedField::Text("quality"),
+        utils::AllowedField::Text("compression"),
+        utils::AllowedField::Raw("photo"),
+        utils::AllowedField::Raw("mask"),
     ];
 
-    let mut multipart_form_data = utils::get_multipart_form_data(content_type, data, fields).await;
-    let left = utils::save_image(&mut multipart_form_data, "left").map_err(|e|
-        status::BadRequest(Some(e))
-    )?;
-    let right = utils::save_image(&mut multipart_form_data, "right").map_err(|e|
-        status::BadRequest(Some(e))
-    )?;
-
-    let dest = {
-        use rand::Rng;
-        let mut rng = rand::thread_rng();
-        let random_name: String = (0..15).map(|_| rng.sample(rand::distributions::Alphanumeric) as char).collect();
-        std::path::PathBuf::from(format!("static/images/{}.png", random_name))
+    let mut multipart_form_data = utils::get_multipart_form_data(content_type, data, fields, config).await?;
+    let photo = Upload::from(utils::get_raw(&mut multipart_form_data, "photo")?);
+    let (config, store, cache, accept) = (config.inner().clone(), store.inner().clone(), cache.inner().clone(), accept.cloned());
+    let apply = {
+        let (config, store) = (config.clone(), store.clone());
+        blocking(move || read_form(&mut multipart_form_data, photo, true, accept.as_ref(), &config, &store.presets())).await?
+    };
+
+    submit_apply(config, store, cache, jobs, apply)
+}
+
+#[post("/jobs", format = "json", data = "<data>", rank = 1)]
+async fn submit_job_json(accept: Option<&Accept>, config: &State<Config>, store: &State<ImageStore>, cache: &State<ResultCache>, jobs: &State<JobQueue>, data: Data<'_>) -> Result<JobCreated, FilterError> {
+    let mut request: ApplyRequest = request::get_json(data, config, 2).await?;
+
+    let (config, store, cache, accept) = (config.inner().clone(), store.inner().clone(), cache.inner().clone(), accept.cloned());
+    let apply = {
+        let (config, store) = (config.clone(), store.clone());
+        blocking(move || {
+            let photo = request::get_upload(&store, &config, "photo", request.photo.take())?;
+            read_request(request, photo, true, accept.as_ref(), &config, &store)
+        }).await?
     };
 
-    filter::compare_images(&left, &right, &dest).map_err(|e|
-        status::BadRequest(Some(e.to_string()))
-    )?;
+    submit_apply(config, store, cache, jobs, apply)
+}
+
+fn submit_apply(config: Config, store: ImageStore, cache: ResultCache, jobs: &JobQueue, apply: Apply) -> Result<JobCreated, FilterError> {
+    let job = jobs.submit(Box::new(move |ctx| {
+        apply_upload(&config, &store, &cache, apply, ctx)?.location
+            .ok_or_else(|| FilterError::Other(String::from("The result wasn't stored.")))
+    }))?;
+
+    Ok(JobCreated::new(job))
+}
+
+#[get("/jobs/<id>")]
+fn job_status(id: &str, jobs: &State<JobQueue>) -> Result<Json<Job>, FilterError> {
+    jobs.get(id)
+        .map(Json)
+        .ok_or_else(|| FilterError::UnknownJob(id.to_owned()))
+}
+
+#[delete("/jobs/<id>")]
+fn cancel_job(id: &str, jobs: &State<JobQueue>) -> Result<status::NoContent, FilterError> {
+    match jobs.cancel(id) {
+        true => Ok(status::NoContent),
+        false => Err(FilterError::UnknownJob(id.to_owned())),
+    }
+}
+
+#[post("/compare", data = "<data>", rank = 2)]
+async fn compare(content_type: Option<&ContentType>, accept: Option<&Accept>, config: &State<Config>, store: &State<ImageStore>, data: Data<'_>) -> Result<ImageResponse, FilterError> {
+    let fields = vec![
+        utils::AllowedField::Text("format"),
+        utils::AllowedField::Text("quality"),
+        utils::AllowedField::Text("compression"),
+        utils::AllowedField::Raw("left"),
+        utils::AllowedField::Raw("right"),
+    ];
+
+    let mut multipart_form_data = utils::get_multipart_form_data(content_type, data, fields, config).await?;
+    let left = Upload::from(utils::get_raw(&mut multipart_form_data, "left")?);
+    let right = Upload::from(utils::get_raw(&mut multipart_form_data, "right")?);
+    let output = utils::get_output(&mut multipart_form_data, accept, filter::Format::Png)?;
+
+    let (config, store) = (config.inner().clone(), store.inner().clone());
+    blocking(move || compare_uploads(&config, &store, left, right, output)).await
+}
+
+#[post("/compare", format = "json", data = "<data>", rank = 1)]
+async fn compare_json(accept: Option<&Accept>, config: &State<Config>, store: &State<ImageStore>, data: Data<'_>) -> Result<ImageResponse, FilterError> {
+    let request: CompareRequest = request::get_json(data, config, 2).await?;
+
+    let (config, store, accept) = (config.inner().clone(), store.inner().clone(), accept.cloned());
+    blocking(move || {
+        let left = request::get_upload(&store, &config, "left", request.left)?;
+        let right = request::get_upload(&store, &config, "right", request.right)?;
+        let output = utils::parse_output(request.format.as_deref(), request.quality, request.compression.as_deref(), accept.as_ref(), filter::Format::Png)?;
+
+        compare_uploads(&config, &store, left, right, output)
+    }).await
+}
+
+#[post("/images/<left>/compare/<right>?<output..>")]
+async fn compare_stored(left: &str, right: &str, output: OutputQuery, accept: Option<&Accept>, config: &State<Config>, store: &State<ImageStore>) -> Result<ImageResponse, FilterError> {
+    let (config, store, accept) = (config.inner().clone(), store.inner().clone(), accept.cloned());
+    let (left, right) = (left.to_owned(), right.to_owned());
+    blocking(move || {
+        let left = request::get_upload(&store, &config, "left", Some(ImageSource::Id(left)))?;
+        let right = request::get_upload(&store, &config, "right", Some(ImageSource::Id(right)))?;
+        let output = utils::parse_output(output.format.as_deref(), output.quality, output.compression.as_deref(), accept.as_ref(), filter::Format::Png)?;
+
+        compare_uploads(&config, &store, left, right, output)
+    }).await
+}
+
+fn compare_uploads(config: &Config, store: &ImageStore, left: Upload, right: Upload, output: (filter::Format, filter::EncodeOptions)) -> Result<ImageResponse, FilterError> {
+    let (format, options) = output;
+
+    let left_buffer = filter::decode_with(&left.bytes, &config.limits())?;
+    let right_buffer = filter::decode_with(&right.bytes, &config.limits())?;
+    let buffer = filter::compare(&left_buffer, &right_buffer)?;
+    let bytes = filter::encode_with(&buffer, format, &options)?;
 
-    NamedFile::open(&dest).await.map_err(|e|
-        status::BadRequest(Some(e.to_string()))
-    )
+    // only stored once the comparison succeeded, not to keep the uploads of a failing request
+    let sources = vec![
+        left.store(store)?,
+        right.store(store)?,
+    ];
+    let lineage = Lineage::new("compare", json!({
+        "format": format.extension(),
+        "quality": options.quality,
+        "compression": options.compression.to_string(),
+    }));
+    let location = Some(format!("/public/{}", store.put_derived(&bytes, sources, lineage)?));
+
+    Ok(ImageResponse { bytes, format, location, cached: None })
+}
+
+#[post("/blend", data = "<data>")]
+async fn blend(content_type: Option<&ContentType>, config: &State<Config>, store: &State<ImageStore>, data: Data<'_>) -> Result<ImageResponse, FilterError> {
+    let fields = vec![
+        utils::AllowedField::Text("mode"),
+        utils::AllowedField::Text("opacity"),
+        utils::AllowedField::Raw("base"),
+        utils::AllowedField::Raw("layer"),
+    ];
+
+    let mut multipart_form_data = utils::get_multipart_form_data(content_type, data, fields, config).await?;
+    let base = utils::get_raw(&mut multipart_form_data, "base")?;
+    let layer = utils::get_raw(&mut multipart_form_data, "layer")?;
+    let (mode, opacity) = utils::get_blend(&mut multipart_form_data)?;
+
+    let (config, store) = (config.inner().clone(), store.inner().clone());
+    blocking(move || {
+        let base_buffer = filter::decode_with(&base.raw, &config.limits())?;
+        let layer_buffer = filter::decode_with(&layer.raw, &config.limits())?;
+        let buffer = filter::blend(&base_buffer, &layer_buffer, mode, opacity)?;
+        let format = filter::Format::Png;
+        let bytes = filter::encode(&buffer, format)?;
+
+        let sources = vec![
+            store.put(&base.raw, base.file_name)?,
+            store.put(&layer.raw, layer.file_name)?,
+        ];
+        let lineage = Lineage::new("blend", json!({ "mode": mode.to_string(), "opacity": opacity }));
+        let location = Some(format!("/public/{}", store.put_derived(&bytes, sources, lineage)?));
+
+        Ok(ImageResponse { bytes, format, location, cached: None })
+    }).await
+}
+
+#[get("/images?<page>&<per_page>")]
+async fn list_images(page: Option<usize>, per_page: Option<usize>, store: &State<ImageStore>) -> Result<Json<Value>, FilterError> {
+    let page = page.unwrap_or(1);
+    let per_page = per_page.unwrap_or(20);
+    if page == 0 {
+        return Err(FilterError::invalid_field("page", "pages start at 1"));
+    }
+    if !(1..=100).contains(&per_page) {
+        return Err(FilterError::invalid_field("per_page", format!("{} isn't between 1 and 100", per_page)));
+    }
+    let offset = (page - 1).checked_mul(per_page)
+        .ok_or_else(|| FilterError::invalid_field("page", format!("{} is too large", page)))?;
+
+    let store = store.inner().clone();
+    let (images, total) = blocking(move || store.list(offset, per_page)).await?;
+    let images = images.into_iter()
+        .map(|(id, meta)| ImageEntry::new(id, meta))
+        .collect::<Vec<_>>();
+
+    Ok(Json(json!({
+        "images": images,
+        "page": page,
+        "per_page": per_page,
+        "total": total,
+    })))
+}
+
+#[get("/images/<id>/meta")]
+async fn image_meta(id: &str, store: &State<ImageStore>) -> Result<Json<ImageEntry>, FilterError> {
+    store::check_name("id", id)?;
+
+    let (store, key) = (store.inner().clone(), id.to_owned());
+    match blocking(move || store.meta(&key)).await? {
+        Some(meta) => Ok(Json(ImageEntry::new(id.to_owned(), meta))),
+        None => Err(FilterError::UnknownImage { field: String::from("id"), id: id.to_owned() }),
+    }
+}
+
+#[get("/images/<id>/lineage")]
+async fn image_lineage(id: &str, store: &State<ImageStore>) -> Result<Json<Value>, FilterError> {
+    store::check_name("id", id)?;
+
+    let (store, key) = (store.inner().clone(), id.to_owned());
+    blocking(move || store.lineage(&key)).await?
+        .map(Json)
+        .ok_or_else(|| FilterError::UnknownImage { field: String::from("id"), id: id.to_owned() })
+}
+
+#[delete("/images/<id>")]
+async fn delete_image(id: &str, store: &State<ImageStore>) -> Result<status::NoContent, FilterError> {
+    store::check_name("id", id)?;
+
+    let (store, key) = (store.inner().clone(), id.to_owned());
+    if blocking(move || store.delete(&key)).await? {
+        Ok(status::NoContent)
+    } else {
+        Err(FilterError::UnknownImage { field: String::from("id"), id: id.to_owned() })
+    }
+}
+
+#[put("/images/<id>/pin")]
+async fn pin_image(id: &str, store: &State<ImageStore>) -> Result<status::NoContent, FilterError> {
+    set_pinned(id, store, true).await
+}
+
+#[delete("/images/<id>/pin")]
+async fn unpin_image(id: &str, store: &State<ImageStore>) -> Result<status::NoContent, FilterError> {
+    set_pinned(id, store, false).await
+}
+
+async fn set_pinned(id: &str, store: &ImageStore, pinned: bool) -> Result<status::NoContent, FilterError> {
+    store::check_name("id", id)?;
+
+    let (store, key) = (store.clone(), id.to_owned());
+    if blocking(move || store.pin(&key, pinned)).await? {
+        Ok(status::NoContent)
+    } else {
+        Err(FilterError::UnknownImage { field: String::from("id"), id: id.to_owned() })
+    }
+}
+
+#[post("/presets", data = "<data>")]
+async fn create_preset(config: &State<Config>, store: &State<ImageStore>, data: Data<'_>) -> Result<status::Created<Json<Preset>>, FilterError> {
+    let request: PresetRequest = request::get_json(data, config, 0).await?;
+    let preset = request.into_preset(config)?;
+    let presets = store.presets();
+    let preset = blocking(move || presets.create(&preset).map(|_| preset)).await?;
+
+    Ok(status::Created::new(format!("/presets/{}", preset.name)).body(Json(preset)))
+}
+
+#[get("/presets")]
+async fn list_presets(store: &State<ImageStore>) -> Result<Json<Vec<Preset>>, FilterError> {
+    let presets = store.presets();
+    blocking(move || presets.list()).await.map(Json)
+}
+
+#[get("/presets/<name>")]
+async fn get_preset(name: &str, store: &State<ImageStore>) -> Result<Json<Preset>, FilterError> {
+    presets::check_name("name", name)?;
+
+    let (presets, key) = (store.presets(), name.to_owned());
+    blocking(move || presets.get(&key)).await?
+        .map(Json)
+        .ok_or_else(|| FilterError::UnknownPreset(name.to_owned()))
+}
+
+#[delete("/presets/<name>")]
+async fn delete_preset(name: &str, store: &State<ImageStore>) -> Result<status::NoContent, FilterError> {
+    presets::check_name("name", name)?;
+
+    let (presets, key) = (store.presets(), name.to_owned());
+    if blocking(move || presets.delete(&key)).await? {
+        Ok(status::NoContent)
+    } else {
+        Err(FilterError::UnknownPreset(name.to_owned()))
+    }
+}
+
+#[get("/<id>")]
+async fn public_image(id: &str, store: &State<ImageStore>) -> Result<Option<(ContentType, Vec<u8>)>, FilterError> {
+    store::check_name("id", id)?;
+
+    let (store, key) = (store.inner().clone(), id.to_owned());
+    let (meta, bytes) = match blocking(move || Ok((store.meta(&key)?, store.read(&key)?))).await? {
+        (Some(meta), Some(bytes)) => (meta, bytes),
+        _ => return Ok(None),
+    };
+    let content_type = ContentType::parse_flexible(&meta.mime).unwrap_or(ContentType::Binary);
+
+    Ok(Some((content_type, bytes)))
 }
 
 #[get("/")]
-fn index_public() -> Template {
+async fn index_public(store: &State<ImageStore>) -> Result<Template, FilterError> {
     #[derive(serde::Serialize)]
     struct Data { images: Vec<String> }
-    let mut vec = Vec::with_capacity(10);
 
-    for entry in std::fs::read_dir("static/images").unwrap() {
-        let full_name = entry.unwrap().file_name();
-        vec.push(full_name.into_string().unwrap());
-    }
+    let store = store.inner().clone();
+    Ok(Template::render("index", Data {
+        images: blocking(move || store.ids()).await?,
+    }))
+}
 
-    Template::render("index", Data {
-        images: vec,
-    })
+// the storage may be remote and the filters slow, so they run on a blocking thread, not to hold a worker of rocket
+async fn blocking<T: Send + 'static>(task: impl FnOnce() -> Result<T, FilterError> + Send + 'static) -> Result<T, FilterError> {
+    rocket::tokio::task::spawn_blocking(task).await
+        .map_err(|_| FilterError::Other(String::from("The task crashed.")))?
 }
 
-#[rocket::main]
-async fn main() -> Result<(), rocket::Error> {
+#[catch(default)]
+fn default_catcher(status: Status, _request: &Request) -> status::Custom<Json<Value>> {
+    let message = status.reason().unwrap_or("Unknown error");
+    let code = message.to_lowercase().replace(|c: char| !c.is_alphanumeric(), "_");
+
+    status::Custom(status, Json(json!({
+        "code": code,
+        "field": null,
+        "details": null,
+        "message": message,
+    })))
+}
+
+#[launch]
+fn rocket() -> _ {
+    build()
+}
+
+fn build() -> Rocket<Build> {
     rocket::build()
-        .mount("/public", FileServer::from("static/images"))
-        .mount("/style", FileServer::from("static/style"))
-        .mount("/", routes![index, save, apply, compare])
-        .mount("/public", routes![index_public])
+        .register("/", catchers![default_catcher])
+        .mount("/", routes![
+            index, algorithms, save, apply, apply_json, apply_stored, apply_stored_json,
+            compare, compare_json, compare_stored, blend,
+            list_images, image_meta, image_lineage, delete_image, pin_image, unpin_image,
+            submit_job, submit_job_json, job_status, cancel_job, apply_batch,
+            create_preset, list_presets, get_preset, delete_preset,
+        ])
+        .mount("/public", routes![index_public, public_image])
+        .attach(AdHoc::config::<Config>())
+        .attach(AdHoc::try_on_ignite("Storage", |rocket| async {
+            let config = rocket.state::<Config>().expect("the config is managed first");
+            if let Err(e) = config.check() {
+                error!("{}", e);
+                return Err(rocket);
+            }
+
+            let style = config.style_dir.relative();
+            if !style.is_dir() {
+                error!("`style_dir` {:?} isn't a directory", style);
+                return Err(rocket);
+            }
+
+            let storage = match storage::open(config) {
+                Ok(storage) => storage,
+                Err(e) => {
+                    error!("{}", e);
+                    return Err(rocket);
+                },
+            };
+
+            match ImageStore::open(storage) {
+                Ok(store) => Ok(rocket.mount("/style", FileServer::from(style)).manage(store)),
+                Err(e) => {
+                    error!("{}", e.get_error_string());
+                    Err(rocket)
+                },
+            }
+        }))
+        .attach(AdHoc::on_ignite("Result cache and jobs", |rocket| async {
+            let config = rocket.state::<Config>().expect("the config is managed first");
+            let cache = ResultCache::new(config);
+            let jobs = JobQueue::new(config.workers, config.job_queue_size);
+            rocket.manage(cache).manage(jobs)
+        }))
+        .attach(retention::reaper())
         .attach(Template::fairing())
-        .launch()
-        .await
 }
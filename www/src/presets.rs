@@ -0,0 +1,236 @@
+use crate::config::Config;
+use crate::file::FilterError;
+use crate::storage::Storage;
+use crate::store;
+use crate::utils;
+
+use std::sync::Arc;
+
+use filter::Algorithms;
+use rocket::serde::{Deserialize, Serialize, json::{json, serde_json, Value}};
+
+// next to the images, so every instance sharing the storage has the same presets
+const PRESET_PREFIX: &str = ".presets/";
+const MAX_STEPS: usize = 16;
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Preset {
+    pub name: String,
+    pub description: Option<String>,
+    pub steps: Vec<Step>,
+    // seconds since the unix epoch
+    pub created_at: u64,
+}
+
+// an algorithm and its parameters, like `--then` of the command line it can't have the ones it doesn't need
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", deny_unknown_fields)]
+pub struct Step {
+    pub algorithm: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub radius: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub factor: Option<i32>,
+}
+
+impl Step {
+    // `index` of the step in `steps`, to name its fields in the errors
+    fn algo(&self, index: Option<usize>, config: &Config) -> Result<Algorithms, FilterError> {
+        let algo = Algorithms::try_from(self.algorithm.as_str())?;
+        config.allow(&algo)?;
+
+        let unneeded = match (self.radius, self.factor) {
+            (Some(_), _) if !algo.need_radius() => Some("radius"),
+            (_, Some(_)) if !algo.need_factor() => Some("factor"),
+            _ => None,
+        };
+        if let Some(parameter) = unneeded {
+            let field = match index {
+                Some(index) => format!("steps[{}].{}", index, parameter),
+                None => String::from(parameter),
+            };
+            return Err(FilterError::invalid_field(&field, format!("\"{}\" has no {}", algo.info().name, parameter)));
+        }
+
+        utils::with_parameters(algo, self.radius, self.factor)
+    }
+
+    // every parameter written down, so a change of the defaults doesn't change the preset
+    fn from_algo(algo: &Algorithms) -> Self {
+        let arguments = algo.arguments();
+        let argument = |name| arguments.iter().find(|(parameter, _)| *parameter == name).map(|(_, value)| *value);
+
+        Self {
+            algorithm: String::from(algo.info().name),
+            radius: argument("radius").map(|radius| radius as u32),
+            factor: argument("factor").map(|factor| factor as i32),
+        }
+    }
+}
+
+// body of `POST /presets`, either `steps` or a single algorithm
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde", deny_unknown_fields)]
+pub struct PresetRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub steps: Option<Vec<Step>>,
+    pub algorithm: Option<String>,
+    pub radius: Option<u32>,
+    pub factor: Option<i32>,
+}
+
+impl PresetRequest {
+    pub fn into_preset(self, config: &Config) -> Result<Preset, FilterError> {
+        let name = self.name.ok_or_else(|| FilterError::MissingField(String::from("name")))?;
+        check_name("name", &name)?;
+
+        let steps = match (self.steps, self.algorithm) {
+            (Some(_), Some(_)) => return Err(FilterError::invalid_field("steps", "can't be used with \"algorithm\"")),
+            (Some(steps), None) => check_steps(&steps, config)?,
+            (None, Some(algorithm)) => vec![Step { algorithm, radius: self.radius, factor: self.factor }.algo(None, config)?],
+            (None, None) => return Err(FilterError::MissingField(String::from("steps"))),
+        };
+        let steps = steps.iter()
+            .map(Step::from_algo)
+            .collect();
+
+        Ok(Preset { name, description: self.description, steps, created_at: store::now() })
+    }
+}
+
+// what `/apply` runs: a single algorithm, the steps of a preset or the ones of the request
+#[derive(Clone)]
+pub struct Operation {
+    pub steps: Vec<Algorithms>,
+    pub preset: Option<String>,
+}
+
+impl Operation {
+    pub fn single(algo: Algorithms) -> Self {
+        Self { steps: vec![algo], preset: None }
+    }
+
+    // the `steps` given with the request, checked like the ones of a new preset
+    pub fn inline(steps: &[Step], config: &Config) -> Result<Self, FilterError> {
+        Ok(Self { steps: check_steps(steps, config)?, preset: None })
+    }
+
+    // for the lineage and the manifests: the `algorithm` and its `parameters`,
+    // or every `steps` for the presets with several of them
+    pub fn describe(&self) -> Value {
+        let step = |algo: &Algorithms| json!({
+            "algorithm": algo.info().name,
+            "parameters": utils::arguments(algo),
+        });
+
+        let mut description = match &self.steps[..] {
+            [algo] => step(algo),
+            steps => json!({ "steps": steps.iter().map(step).collect::<Vec<_>>() }),
+        };
+        if let Some(preset) = &self.preset {
+            description["preset"] = json!(preset);
+        }
+
+        description
+    }
+}
+
+#[derive(Clone)]
+pub struct PresetStore {
+    storage: Arc<dyn Storage>,
+}
+
+impl PresetStore {
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        Self { storage }
+    }
+
+    // by name
+    pub fn list(&self) -> Result<Vec<Preset>, FilterError> {
+        let keys = self.storage.list().map_err(storage_error)?;
+        let mut presets = Vec::new();
+        for name in keys.iter().filter_map(|key| key.strip_prefix(PRESET_PREFIX)?.strip_suffix(".json")) {
+            if let Some(preset) = self.get(name)? {
+                presets.push(preset);
+            }
+        }
+
+        presets.sort_by(|lhs, rhs| lhs.name.cmp(&rhs.name));
+        Ok(presets)
+    }
+
+    pub fn get(&self, name: &str) -> Result<Option<Preset>, FilterError> {
+        match self.storage.read(&preset_key(name)).map_err(storage_error)? {
+            Some(preset) => serde_json::from_slice(&preset).map(Some).map_err(|e|
+                FilterError::Other(format!("Failed to read the preset {}: {}", name, e))
+            ),
+            None => Ok(None),
+        }
+    }
+
+    // a preset is never replaced, delete it first
+    pub fn create(&self, preset: &Preset) -> Result<(), FilterError> {
+        if self.get(&preset.name)?.is_some() {
+            return Err(FilterError::PresetExists(preset.name.clone()));
+        }
+
+        let json = serde_json::to_vec(preset).map_err(|e|
+            FilterError::Other(format!("Failed to write the preset {}: {}", preset.name, e))
+        )?;
+        self.storage.write(&preset_key(&preset.name), &json).map_err(storage_error)
+    }
+
+    // returns false when there's no such preset
+    pub fn delete(&self, name: &str) -> Result<bool, FilterError> {
+        if self.get(name)?.is_none() {
+            return Ok(false);
+        }
+
+        self.storage.delete(&preset_key(name)).map_err(storage_error)?;
+        Ok(true)
+    }
+
+    // the steps are checked again, the allowed algorithms may have changed since the preset was saved
+    pub fn operation(&self, name: &str, config: &Config) -> Result<Operation, FilterError> {
+        check_name("preset", name)?;
+        let preset = self.get(name)?.ok_or_else(|| FilterError::UnknownPreset(name.to_owned()))?;
+
+        let steps = preset.steps.iter()
+            .map(|step| step.algo(None, config))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Operation { steps, preset: Some(preset.name) })
+    }
+}
+
+fn check_steps(steps: &[Step], config: &Config) -> Result<Vec<Algorithms>, FilterError> {
+    if steps.is_empty() || steps.len() > MAX_STEPS {
+        return Err(FilterError::invalid_field("steps", format!("there must be between 1 and {} steps", MAX_STEPS)));
+    }
+
+    steps.iter().enumerate().map(|(index, step)| step.algo(Some(index), config)).collect()
+}
+
+// lowercase letters, digits, `_` and `-`, ex: `soft_denoise`
+pub fn check_name(field: &str, name: &str) -> Result<(), FilterError> {
+    let valid = (1..=64).contains(&name.len())
+        && name.chars().all(|c| matches!(c, 'a'..='z' | '0'..='9' | '_' | '-'));
+
+    if !valid {
+        return Err(FilterError::invalid_field(field, format!(
+            "\"{}\" must have 1 to 64 lowercase letters, digits, \"_\" or \"-\"", name.escape_default()
+        )));
+    }
+
+    Ok(())
+}
+
+fn preset_key(name: &str) -> String {
+    format!("{}{}.json", PRESET_PREFIX, name)
+}
+
+fn storage_error(e: std::io::Error) -> FilterError {
+    FilterError::Other(format!("Failed to access the presets: {}", e))
+}
@@ -0,0 +1,139 @@
+use crate::config::Config;
+use crate::file::FilterError;
+use crate::presets::{Operation, Step};
+use crate::store::{self, ImageStore};
+
+use rocket::data::{Data, ToByteUnit};
+use filter::{EncodeOptions, Format, Roi};
+use rocket::serde::{Deserialize, DeserializeOwned, Serialize, json::serde_json};
+use rocket_multipart_form_data::RawField;
+
+// an image sent with the request, either as a file field or in a json body
+pub struct Upload {
+    pub bytes: Vec<u8>,
+    pub file_name: Option<String>,
+    // set when it's an image already stored, given by its id
+    pub id: Option<String>,
+}
+
+impl From<RawField> for Upload {
+    fn from(field: RawField) -> Self {
+        Self { bytes: field.raw, file_name: field.file_name, id: None }
+    }
+}
+
+impl Upload {
+    // the id of the image, stored first unless it already was
+    pub fn store(self, store: &ImageStore) -> Result<String, FilterError> {
+        match self.id {
+            Some(id) => Ok(id),
+            None => store.put(&self.bytes, self.file_name),
+        }
+    }
+}
+
+// `{"data": "<base64>"}` or `{"id": "<id of a stored image>"}`
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum ImageSource {
+    Data(String),
+    Id(String),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl From<Rect> for Roi {
+    fn from(rect: Rect) -> Self {
+        Roi::Rect { x: rect.x, y: rect.y, width: rect.width, height: rect.height }
+    }
+}
+
+// everything `/apply` needs, whatever the body it was read from
+pub struct Apply {
+    pub photo: Upload,
+    pub operation: Operation,
+    // either a rectangle or a mask, never both
+    pub rect: Option<Rect>,
+    pub mask: Option<Upload>,
+    pub persist: bool,
+    pub format: Format,
+    pub options: EncodeOptions,
+}
+
+// same fields as the multipart body of `/apply`
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde", deny_unknown_fields)]
+pub struct ApplyRequest {
+    pub photo: Option<ImageSource>,
+    pub preset: Option<String>,
+    pub steps: Option<Vec<Step>>,
+    pub algorithm: Option<String>,
+    pub radius: Option<u32>,
+    pub factor: Option<i32>,
+    pub roi: Option<Rect>,
+    pub mask: Option<ImageSource>,
+    #[serde(default)]
+    pub persist: bool,
+    pub format: Option<String>,
+    pub quality: Option<u8>,
+    pub compression: Option<String>,
+}
+
+// same fields as the multipart body of `/compare`
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde", deny_unknown_fields)]
+pub struct CompareRequest {
+    pub left: Option<ImageSource>,
+    pub right: Option<ImageSource>,
+    pub format: Option<String>,
+    pub quality: Option<u8>,
+    pub compression: Option<String>,
+}
+
+// output options of the routes without any body
+#[derive(FromForm)]
+pub struct OutputQuery {
+    pub format: Option<String>,
+    pub quality: Option<u8>,
+    pub compression: Option<String>,
+}
+
+// `images` is the number of images the body can hold, base64 makes them a third larger
+pub async fn get_json<T: DeserializeOwned>(data: Data<'_>, config: &Config, images: u64) -> Result<T, FilterError> {
+    let limit = config.max_upload_size * images * 4 / 3 + 64.kibibytes();
+    let body = data.open(limit).into_bytes().await.map_err(|e| FilterError::Other(e.to_string()))?;
+
+    if !body.is_complete() {
+        return Err(FilterError::TooLarge { field: String::from("body"), limit });
+    }
+
+    serde_json::from_slice(&body).map_err(|e| FilterError::MalformedBody(e.to_string()))
+}
+
+pub fn get_upload(store: &ImageStore, config: &Config, field: &str, source: Option<ImageSource>) -> Result<Upload, FilterError> {
+    match source {
+        None => Err(FilterError::MissingField(String::from(field))),
+        Some(ImageSource::Data(data)) => {
+            let bytes = base64::decode(data).map_err(|e| FilterError::invalid_field(field, e))?;
+            if bytes.len() as u64 > config.max_upload_size.as_u64() {
+                return Err(FilterError::TooLarge { field: String::from(field), limit: config.max_upload_size });
+            }
+
+            Ok(Upload { bytes, file_name: None, id: None })
+        },
+        Some(ImageSource::Id(id)) => {
+            store::check_name(field, &id)?;
+            match store.read(&id)? {
+                Some(bytes) => Ok(Upload { bytes, file_name: None, id: Some(id) }),
+                None => Err(FilterError::UnknownImage { field: String::from(field), id }),
+            }
+        },
+    }
+}
@@ -0,0 +1,64 @@
+use crate::jobs::Job;
+
+use rocket::{
+    http::{ContentType, Header}, request::Request, response::{self, Responder}, serde::json::Json,
+};
+
+pub struct ImageResponse {
+    pub bytes: Vec<u8>,
+    pub format: filter::Format,
+    // url of the stored copy, if any
+    pub location: Option<String>,
+    // whether it came from the result cache, none when the cache wasn't used
+    pub cached: Option<bool>,
+}
+
+impl<'r> Responder<'r, 'static> for ImageResponse {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let content_type = ContentType::parse_flexible(self.format.mime())
+            .unwrap_or(ContentType::Binary);
+        let mut response = (content_type, self.bytes).respond_to(request)?;
+
+        if let Some(location) = self.location {
+            response.set_header(Header::new("Location", location));
+        }
+
+        if let Some(cached) = self.cached {
+            response.set_header(Header::new("X-Cache", if cached { "HIT" } else { "MISS" }));
+        }
+
+        Ok(response)
+    }
+}
+
+// 202 with the url of the job to poll
+#[derive(Responder)]
+#[response(status = 202)]
+pub struct JobCreated {
+    job: Json<Job>,
+    location: Header<'static>,
+}
+
+impl JobCreated {
+    pub fn new(job: Job) -> Self {
+        Self { location: Header::new("Location", format!("/jobs/{}", job.id)), job: Json(job) }
+    }
+}
+
+// a zip, downloaded as `file_name`
+#[derive(Responder)]
+pub struct ArchiveResponse {
+    archive: Vec<u8>,
+    content_type: ContentType,
+    disposition: Header<'static>,
+}
+
+impl ArchiveResponse {
+    pub fn new(archive: Vec<u8>, file_name: &str) -> Self {
+        Self {
+            archive,
+            content_type: ContentType::ZIP,
+            disposition: Header::new("Content-Disposition", format!("attachment; filename=\"{}\"", file_name)),
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use crate::config::Config;
+use crate::store::{self, ImageStore};
+
+use std::thread;
+use std::time::Duration;
+
+use rocket::fairing::AdHoc;
+
+// what the reaper deletes, see `ImageStore::sweep`
+#[derive(Clone, Copy)]
+pub struct Retention {
+    // in seconds, 0 keeps the derived images
+    pub derived_ttl: u64,
+    // in bytes, 0 for no quota
+    pub quota: u64,
+}
+
+impl Retention {
+    pub fn new(config: &Config) -> Self {
+        Self { derived_ttl: config.derived_ttl, quota: config.storage_quota.as_u64() }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.derived_ttl > 0 || self.quota > 0
+    }
+}
+
+// sweeps the stored images every `reaper_interval` seconds, once the server is started
+pub fn reaper() -> AdHoc {
+    AdHoc::on_liftoff("Reaper", |rocket| Box::pin(async move {
+        let config = rocket.state::<Config>().expect("the config is managed first");
+        let store = rocket.state::<ImageStore>().expect("the store is managed first").clone();
+        let retention = Retention::new(config);
+        let interval = Duration::from_secs(config.reaper_interval.max(1));
+
+        if !retention.enabled() {
+            return;
+        }
+
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+
+            match store.sweep(&retention, store::now()) {
+                Ok(deleted) if !deleted.is_empty() => info!("Reaper: deleted {} images", deleted.len()),
+                Ok(_) => {},
+                Err(e) => error!("Reaper: {}", e.get_error_string()),
+            }
+        });
+    }))
+}
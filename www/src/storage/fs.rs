@@ -0,0 +1,73 @@
+use super::Storage;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// a key is a path under `root`
+pub struct FsStorage {
+    root: PathBuf,
+}
+
+impl FsStorage {
+    pub fn new(root: impl Into<PathBuf>) -> io::Result<Self> {
+        let root = root.into();
+        fs::create_dir_all(&root)?;
+
+        Ok(Self { root })
+    }
+}
+
+impl Storage for FsStorage {
+    fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        match fs::read(self.root.join(key)) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn exists(&self, key: &str) -> io::Result<bool> {
+        self.root.join(key).try_exists()
+    }
+
+    fn write(&self, key: &str, bytes: &[u8]) -> io::Result<()> {
+        let path = self.root.join(key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, bytes)
+    }
+
+    fn delete(&self, key: &str) -> io::Result<()> {
+        match fs::remove_file(self.root.join(key)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn list(&self) -> io::Result<Vec<String>> {
+        let mut keys = Vec::new();
+        list_dir(&self.root, "", &mut keys)?;
+        Ok(keys)
+    }
+}
+
+fn list_dir(dir: &Path, prefix: &str, keys: &mut Vec<String>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = match entry.file_name().into_string() {
+            Ok(name) => format!("{}{}", prefix, name),
+            Err(_) => continue,
+        };
+
+        if entry.file_type()?.is_dir() {
+            list_dir(&entry.path(), &format!("{}/", name), keys)?;
+        } else {
+            keys.push(name);
+        }
+    }
+
+    Ok(())
+}
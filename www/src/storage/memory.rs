@@ -0,0 +1,35 @@
+use super::Storage;
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::Mutex;
+
+// lost when the server stops, for tests and throwaway instances
+#[derive(Default)]
+pub struct MemoryStorage {
+    objects: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl Storage for MemoryStorage {
+    fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.objects.lock().unwrap().get(key).cloned())
+    }
+
+    fn exists(&self, key: &str) -> io::Result<bool> {
+        Ok(self.objects.lock().unwrap().contains_key(key))
+    }
+
+    fn write(&self, key: &str, bytes: &[u8]) -> io::Result<()> {
+        self.objects.lock().unwrap().insert(key.to_owned(), bytes.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> io::Result<()> {
+        self.objects.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    fn list(&self) -> io::Result<Vec<String>> {
+        Ok(self.objects.lock().unwrap().keys().cloned().collect())
+    }
+}
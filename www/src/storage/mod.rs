@@ -0,0 +1,40 @@
+pub mod fs;
+pub mod memory;
+pub mod s3;
+
+pub use self::fs::FsStorage;
+pub use self::memory::MemoryStorage;
+pub use self::s3::{S3Config, S3Storage};
+
+use crate::config::{Config, StorageKind};
+
+use std::io;
+use std::sync::Arc;
+
+// where the bytes of the images and their metadata are kept, under flat keys like `<id>` or `.meta/<id>.json`.
+// every instance of the server sharing the same storage sees the same images
+pub trait Storage: Send + Sync {
+    // none when there's no such key
+    fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>>;
+    fn exists(&self, key: &str) -> io::Result<bool>;
+    fn write(&self, key: &str, bytes: &[u8]) -> io::Result<()>;
+    // deleting a missing key isn't an error
+    fn delete(&self, key: &str) -> io::Result<()>;
+    // every key, in no particular order
+    fn list(&self) -> io::Result<Vec<String>>;
+}
+
+pub fn open(config: &Config) -> Result<Arc<dyn Storage>, String> {
+    match config.storage {
+        StorageKind::Fs => {
+            let root = config.storage_root.relative();
+            let storage = FsStorage::new(&root).map_err(|e| format!("Failed to create {:?}: {}", root, e))?;
+            Ok(Arc::new(storage))
+        },
+        StorageKind::Memory => Ok(Arc::new(MemoryStorage::default())),
+        StorageKind::S3 => match &config.s3 {
+            Some(s3) => Ok(Arc::new(S3Storage::new(s3.clone())?)),
+            None => Err(String::from("`storage` is \"s3\" but `s3` isn't set")),
+        },
+    }
+}
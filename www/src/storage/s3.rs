@@ -0,0 +1,251 @@
+use super::Storage;
+
+use std::io::{self, Read};
+use std::time::{Duration, SystemTime};
+
+use hmac::{Hmac, Mac};
+use quick_xml::{events::Event, Reader};
+use rocket::serde::Deserialize;
+use sha2::{Digest, Sha256};
+use time::{macros::format_description, OffsetDateTime};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct S3Config {
+    // ex: `http://localhost:9000`, the bucket is in the path
+    pub endpoint: String,
+    pub bucket: String,
+    #[serde(default = "default_region")]
+    pub region: String,
+    pub access_key: String,
+    pub secret_key: String,
+    // prepended to every key, so a bucket can be shared
+    #[serde(default)]
+    pub prefix: String,
+}
+
+fn default_region() -> String {
+    String::from("us-east-1")
+}
+
+// any service speaking the S3 api, signed with AWS signature version 4
+pub struct S3Storage {
+    config: S3Config,
+    // `host:port` of the endpoint, without its scheme
+    host: String,
+    agent: ureq::Agent,
+}
+
+struct Response {
+    status: u16,
+    body: Vec<u8>,
+}
+
+impl S3Storage {
+    pub fn new(config: S3Config) -> Result<Self, String> {
+        let host = match config.endpoint.strip_prefix("http://").or_else(|| config.endpoint.strip_prefix("https://")) {
+            Some(host) => host.trim_end_matches('/').to_owned(),
+            None => return Err(format!("the s3 endpoint \"{}\" must start with http:// or https://", config.endpoint)),
+        };
+        if host.is_empty() || host.contains('/') {
+            return Err(format!("the s3 endpoint \"{}\" must only have a host and a port", config.endpoint));
+        }
+
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(CONNECT_TIMEOUT)
+            .timeout(TIMEOUT)
+            .build();
+
+        Ok(Self { config, host, agent })
+    }
+
+    fn object_path(&self, key: &str) -> String {
+        format!("/{}/{}", uri_encode(&self.config.bucket, false), uri_encode(&format!("{}{}", self.config.prefix, key), false))
+    }
+
+    fn request(&self, method: &str, path: &str, query: &[(&str, &str)], body: &[u8]) -> io::Result<Response> {
+        let mut query = query.iter()
+            .map(|(key, value)| format!("{}={}", uri_encode(key, true), uri_encode(value, true)))
+            .collect::<Vec<_>>();
+        query.sort();
+        let query = query.join("&");
+
+        let payload_hash = hex(&Sha256::digest(body));
+        let amz_date = amz_date(SystemTime::now());
+        let headers = [
+            ("host", self.host.as_str()),
+            ("x-amz-content-sha256", payload_hash.as_str()),
+            ("x-amz-date", amz_date.as_str()),
+        ];
+        let authorization = authorization(&self.config, method, path, &query, &headers, &payload_hash, &amz_date);
+
+        let endpoint = self.config.endpoint.trim_end_matches('/');
+        let url = match query.is_empty() {
+            true => format!("{}{}", endpoint, path),
+            false => format!("{}{}?{}", endpoint, path, query),
+        };
+        let mut request = self.agent.request(method, &url).set("authorization", &authorization);
+        for (name, value) in headers {
+            request = request.set(name, value);
+        }
+
+        // the error statuses come with a body too
+        let response = match request.send_bytes(body) {
+            Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+            Err(e) => return Err(io::Error::other(e)),
+        };
+
+        let status = response.status();
+        let mut body = Vec::new();
+        response.into_reader().read_to_end(&mut body)?;
+        Ok(Response { status, body })
+    }
+
+    fn fail<T>(&self, action: &str, key: &str, response: Response) -> io::Result<T> {
+        let body = String::from_utf8_lossy(&response.body);
+        let code = xml_values(&body, "Code").into_iter().next().unwrap_or_default();
+
+        Err(io::Error::other(format!(
+            "s3 {} of \"{}\" failed with status {} {}", action, key, response.status, code
+        )))
+    }
+}
+
+impl Storage for S3Storage {
+    fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        let response = self.request("GET", &self.object_path(key), &[], &[])?;
+
+        match response.status {
+            200 => Ok(Some(response.body)),
+            404 => Ok(None),
+            _ => self.fail("read", key, response),
+        }
+    }
+
+    fn exists(&self, key: &str) -> io::Result<bool> {
+        let response = self.request("HEAD", &self.object_path(key), &[], &[])?;
+
+        match response.status {
+            200 => Ok(true),
+            404 => Ok(false),
+            _ => self.fail("check", key, response),
+        }
+    }
+
+    fn write(&self, key: &str, bytes: &[u8]) -> io::Result<()> {
+        let response = self.request("PUT", &self.object_path(key), &[], bytes)?;
+
+        match response.status {
+            200 => Ok(()),
+            _ => self.fail("write", key, response),
+        }
+    }
+
+    fn delete(&self, key: &str) -> io::Result<()> {
+        let response = self.request("DELETE", &self.object_path(key), &[], &[])?;
+
+        match response.status {
+            200 | 204 | 404 => Ok(()),
+            _ => self.fail("delete", key, response),
+        }
+    }
+
+    fn list(&self) -> io::Result<Vec<String>> {
+        let path = format!("/{}", uri_encode(&self.config.bucket, false));
+        let mut keys = Vec::new();
+        let mut token: Option<String> = None;
+
+        // the keys come by pages of 1000
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", self.config.prefix.as_str())];
+            if let Some(token) = &token {
+                query.push(("continuation-token", token.as_str()));
+            }
+
+            let response = self.request("GET", &path, &query, &[])?;
+            if response.status != 200 {
+                return self.fail("list", &self.config.prefix, response);
+            }
+
+            let body = String::from_utf8_lossy(&response.body);
+            keys.extend(xml_values(&body, "Key").into_iter()
+                .filter_map(|key| key.strip_prefix(&self.config.prefix).map(String::from)));
+
+            token = match xml_values(&body, "IsTruncated").first().map(String::as_str) {
+                Some("true") => xml_values(&body, "NextContinuationToken").into_iter().next(),
+                _ => None,
+            };
+            if token.is_none() {
+                return Ok(keys);
+            }
+        }
+    }
+}
+
+// the `authorization` header, `headers` are lowercase and sorted by name
+pub fn authorization(config: &S3Config, method: &str, path: &str, query: &str, headers: &[(&str, &str)], payload_hash: &str, amz_date: &str) -> String {
+    let date = &amz_date[..8];
+    let scope = format!("{}/{}/s3/aws4_request", date, config.region);
+    let signed_headers = headers.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+    let canonical_headers = headers.iter().map(|(name, value)| format!("{}:{}\n", name, value.trim())).collect::<String>();
+
+    let canonical_request = format!("{}\n{}\n{}\n{}\n{}\n{}", method, path, query, canonical_headers, signed_headers, payload_hash);
+    let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", amz_date, scope, hex(&Sha256::digest(canonical_request.as_bytes())));
+
+    let key = [date, &config.region, "s3", "aws4_request"].iter()
+        .fold(format!("AWS4{}", config.secret_key).into_bytes(), |key, part| hmac(&key, part.as_bytes()));
+    let signature = hex(&hmac(&key, string_to_sign.as_bytes()));
+
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        config.access_key, scope, signed_headers, signature
+    )
+}
+
+fn hmac(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac takes keys of any size");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// everything but the unreserved characters, and `/` in paths
+fn uri_encode(value: &str, encode_slash: bool) -> String {
+    value.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+            b'/' if !encode_slash => String::from("/"),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+// ex: 20130524T000000Z
+pub fn amz_date(time: SystemTime) -> String {
+    OffsetDateTime::from(time)
+        .format(format_description!("[year][month][day]T[hour][minute][second]Z"))
+        .expect("a date can be formatted")
+}
+
+// the text of every `<tag>`, enough for the few responses of s3
+fn xml_values(xml: &str, tag: &str) -> Vec<String> {
+    let mut reader = Reader::from_str(xml);
+    let mut values = Vec::new();
+    let mut inside = false;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(start)) => inside = start.name().as_ref() == tag.as_bytes(),
+            Ok(Event::Text(text)) if inside => values.extend(text.unescape().map(String::from)),
+            Ok(Event::End(_)) => inside = false,
+            Ok(Event::Eof) | Err(_) => return values,
+            _ => {},
+        }
+    }
+}
@@ -0,0 +1,490 @@
+use crate::file::FilterError;
+use crate::presets::PresetStore;
+use crate::retention::Retention;
+use crate::storage::Storage;
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rocket::serde::{Deserialize, Serialize, json::{json, serde_json, Value}};
+use sha2::{Digest, Sha256};
+
+// one metadata per image, so the instances sharing a storage don't overwrite each other
+const META_PREFIX: &str = ".meta/";
+// the single index of the previous versions, moved to `META_PREFIX` on open
+const INDEX_FILE: &str = ".index.json";
+// in seconds, how often the access time of an image is written to the storage
+const ACCESS_PRECISION: u64 = 60;
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ImageMeta {
+    pub original_name: Option<String>,
+    pub mime: String,
+    pub width: u32,
+    pub height: u32,
+    // in bytes
+    #[serde(default)]
+    pub size: u64,
+    // seconds since the unix epoch
+    #[serde(alias = "uploaded_at")]
+    pub created_at: u64,
+    // ids of the images this one was computed from
+    #[serde(default)]
+    pub derived_from: Vec<String>,
+    // how it was computed, none for uploads
+    #[serde(default)]
+    pub lineage: Option<Lineage>,
+    // pinned images are never deleted by the retention
+    #[serde(default)]
+    pub pinned: bool,
+    // seconds since the unix epoch, the last time it was uploaded or read
+    #[serde(default)]
+    pub accessed_at: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Lineage {
+    pub operation: String,
+    // every parameter used, defaults included
+    pub parameters: Value,
+    pub filter_version: String,
+}
+
+impl Lineage {
+    pub fn new(operation: &str, parameters: Value) -> Self {
+        Self {
+            operation: String::from(operation),
+            parameters,
+            filter_version: String::from(filter::VERSION),
+        }
+    }
+}
+
+impl ImageMeta {
+    fn new(original_name: Option<String>, bytes: &[u8], info: &filter::ImageInfo) -> Self {
+        let created_at = now();
+
+        Self {
+            original_name,
+            mime: String::from(info.mime),
+            width: info.width,
+            height: info.height,
+            size: bytes.len() as u64,
+            created_at,
+            derived_from: Vec::new(),
+            lineage: None,
+            pinned: false,
+            accessed_at: created_at,
+        }
+    }
+
+    // indexes written before the access time was kept
+    fn last_used(&self) -> u64 {
+        self.accessed_at.max(self.created_at)
+    }
+}
+
+// how an image is described by the api
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ImageEntry {
+    pub id: String,
+    pub url: String,
+    #[serde(flatten)]
+    pub meta: ImageMeta,
+}
+
+impl ImageEntry {
+    pub fn new(id: String, meta: ImageMeta) -> Self {
+        Self { url: format!("/public/{}", id), id, meta }
+    }
+}
+
+// images are stored under `<sha256>.<extension>` of their content, that name is their id.
+// the same bytes always get the same id, so an upload is only written once.
+// clones share the same images, to be used from other threads.
+#[derive(Clone)]
+pub struct ImageStore {
+    storage: Arc<dyn Storage>,
+    // the metadata read so far, other instances sharing the storage may have changed it since
+    index: Arc<Mutex<HashMap<String, ImageMeta>>>,
+}
+
+impl ImageStore {
+    pub fn open(storage: Arc<dyn Storage>) -> Result<Self, FilterError> {
+        let store = Self { storage, index: Arc::new(Mutex::new(HashMap::new())) };
+        let keys = store.refresh()?;
+
+        // the single index of the previous versions
+        if let Some(old) = store.storage.read(INDEX_FILE).map_err(storage_error)? {
+            let old: HashMap<String, ImageMeta> = serde_json::from_slice(&old).map_err(|e|
+                FilterError::Other(format!("Failed to read the image index: {}", e))
+            )?;
+
+            for (id, meta) in old {
+                if store.cached(&id).is_none() && keys.contains(&id) {
+                    store.save_meta(&id, &meta)?;
+                    store.index.lock().unwrap().insert(id, meta);
+                }
+            }
+            store.storage.delete(INDEX_FILE).map_err(storage_error)?;
+        }
+
+        // images added without the server keep their name as id
+        for key in keys {
+            if key.starts_with('.') || key.contains('/') || store.cached(&key).is_some() {
+                continue;
+            }
+
+            let bytes = match store.storage.read(&key).map_err(storage_error)? {
+                Some(bytes) => bytes,
+                None => continue,
+            };
+            if let Ok(info) = filter::inspect(&bytes) {
+                let meta = ImageMeta::new(Some(key.clone()), &bytes, &info);
+                store.save_meta(&key, &meta)?;
+                store.index.lock().unwrap().insert(key, meta);
+            }
+        }
+
+        Ok(store)
+    }
+
+    // the presets are kept in the same storage as the images
+    pub fn presets(&self) -> PresetStore {
+        PresetStore::new(self.storage.clone())
+    }
+
+    pub fn put(&self, bytes: &[u8], original_name: Option<String>) -> Result<String, FilterError> {
+        if let Some(name) = &original_name {
+            check_name("file_name", name)?;
+        }
+
+        self.insert(bytes, ImageMeta::new(original_name, bytes, &filter::inspect(bytes)?))
+    }
+
+    // a result computed from the `sources` images
+    pub fn put_derived(&self, bytes: &[u8], sources: Vec<String>, lineage: Lineage) -> Result<String, FilterError> {
+        let mut meta = ImageMeta::new(None, bytes, &filter::inspect(bytes)?);
+        meta.derived_from = sources;
+        meta.lineage = Some(lineage);
+
+        self.insert(bytes, meta)
+    }
+
+    fn insert(&self, bytes: &[u8], meta: ImageMeta) -> Result<String, FilterError> {
+        let extension = filter::inspect(bytes)?.extension;
+        let id = format!("{:x}.{}", Sha256::digest(bytes), extension);
+
+        if let Some(stored) = self.lookup(&id)? {
+            // another instance may have deleted it since it was read, then it's stored again
+            let exists = self.storage.exists(&id).map_err(storage_error)?
+                && self.storage.exists(&meta_key(&id)).map_err(storage_error)?;
+            if exists {
+                // uploaded again, it isn't only a derived image the retention can delete anymore
+                if meta.lineage.is_none() && stored.lineage.is_some() {
+                    let now = now();
+                    self.update_meta(&id, |stored| {
+                        stored.derived_from.clear();
+                        stored.lineage = None;
+                        stored.accessed_at = now;
+                    })?;
+                } else {
+                    self.touch(&id, &stored)?;
+                }
+                return Ok(id);
+            }
+            self.index.lock().unwrap().remove(&id);
+        }
+
+        // the metadata last, an image without it is never served
+        self.storage.write(&id, bytes).map_err(|e|
+            FilterError::Other(format!("An Error occured while saving file: {}", e))
+        )?;
+        self.save_meta(&id, &meta)?;
+        self.index.lock().unwrap().insert(id.clone(), meta);
+
+        Ok(id)
+    }
+
+    // returns false when there's no such image
+    pub fn delete(&self, id: &str) -> Result<bool, FilterError> {
+        if self.lookup(id)?.is_none() {
+            return Ok(false);
+        }
+
+        self.remove(id)?;
+        Ok(true)
+    }
+
+    // counts as a use of the image
+    pub fn read(&self, id: &str) -> Result<Option<Vec<u8>>, FilterError> {
+        match self.lookup(id)? {
+            Some(meta) => self.touch(id, &meta)?,
+            None => return Ok(None),
+        }
+
+        let bytes = self.storage.read(id).map_err(|e|
+            FilterError::Other(format!("Failed to read {}: {}", id, e))
+        )?;
+        // deleted by another instance
+        if bytes.is_none() {
+            self.index.lock().unwrap().remove(id);
+        }
+
+        Ok(bytes)
+    }
+
+    pub fn ids(&self) -> Result<Vec<String>, FilterError> {
+        self.refresh()?;
+
+        let mut ids = self.index.lock().unwrap().keys().cloned().collect::<Vec<_>>();
+        ids.sort();
+        Ok(ids)
+    }
+
+    pub fn meta(&self, id: &str) -> Result<Option<ImageMeta>, FilterError> {
+        self.lookup(id)
+    }
+
+    // returns false when there's no such image
+    pub fn pin(&self, id: &str, pinned: bool) -> Result<bool, FilterError> {
+        if self.lookup(id)?.is_none() {
+            return Ok(false);
+        }
+
+        self.update_meta(id, |meta| meta.pinned = pinned)?;
+        Ok(true)
+    }
+
+    // deletes the derived images unused for `derived_ttl`, then the least recently used ones
+    // until the images fit in `quota`. returns the ids of the deleted images.
+    // an image used or pinned by another instance since it was read is kept, until the next sweep
+    pub fn sweep(&self, retention: &Retention, now: u64) -> Result<Vec<String>, FilterError> {
+        self.refresh()?;
+
+        let expired = {
+            let index = self.index.lock().unwrap();
+            let mut candidates = index.iter()
+                .filter(|(_, meta)| !meta.pinned)
+                .map(|(id, meta)| (meta.last_used(), id.clone()))
+                .collect::<Vec<_>>();
+            candidates.sort();
+
+            let mut total = index.values().map(|meta| meta.size).sum::<u64>();
+            let mut expired = Vec::new();
+            for (last_used, id) in candidates {
+                let meta = &index[&id];
+                let too_old = retention.derived_ttl > 0
+                    && meta.lineage.is_some()
+                    && last_used + retention.derived_ttl <= now;
+                let over_quota = retention.quota > 0 && total > retention.quota;
+
+                if too_old || over_quota {
+                    total -= meta.size;
+                    expired.push((id, last_used));
+                }
+            }
+            expired
+        };
+
+        let mut deleted = Vec::new();
+        for (id, last_used) in expired {
+            match self.read_meta(&id)? {
+                Some(meta) if meta.pinned || meta.last_used() > last_used => {
+                    self.index.lock().unwrap().insert(id, meta);
+                },
+                _ => {
+                    self.remove(&id)?;
+                    deleted.push(id);
+                },
+            }
+        }
+
+        Ok(deleted)
+    }
+
+    // the whole history of an image, down to the uploads it came from
+    pub fn lineage(&self, id: &str) -> Result<Option<Value>, FilterError> {
+        self.refresh()?;
+
+        let index = self.index.lock().unwrap();
+        Ok(index.get(id).map(|meta| lineage_tree(&index, id, meta)))
+    }
+
+    // the newest images first
+    pub fn list(&self, offset: usize, limit: usize) -> Result<(Vec<(String, ImageMeta)>, usize), FilterError> {
+        self.refresh()?;
+
+        let index = self.index.lock().unwrap();
+        let mut images = index.iter().collect::<Vec<_>>();
+        images.sort_by(|(lhs_id, lhs), (rhs_id, rhs)| rhs.created_at.cmp(&lhs.created_at).then(lhs_id.cmp(rhs_id)));
+
+        let page = images.into_iter()
+            .skip(offset)
+            .take(limit)
+            .map(|(id, meta)| (id.clone(), meta.clone()))
+            .collect();
+
+        Ok((page, index.len()))
+    }
+
+    // the index is only locked around its own changes, never while the storage is accessed
+    fn cached(&self, id: &str) -> Option<ImageMeta> {
+        self.index.lock().unwrap().get(id).cloned()
+    }
+
+    // the metadata of `id`, read from the storage when another instance stored it
+    fn lookup(&self, id: &str) -> Result<Option<ImageMeta>, FilterError> {
+        if let Some(meta) = self.cached(id) {
+            return Ok(Some(meta));
+        }
+
+        let meta = self.read_meta(id)?;
+        if let Some(meta) = &meta {
+            self.index.lock().unwrap().insert(id.to_owned(), meta.clone());
+        }
+        Ok(meta)
+    }
+
+    // the access time is only written every `ACCESS_PRECISION` seconds, not on every read
+    fn touch(&self, id: &str, meta: &ImageMeta) -> Result<(), FilterError> {
+        let now = now();
+        if meta.last_used() + ACCESS_PRECISION > now {
+            return Ok(());
+        }
+
+        // the concurrent reads don't all write it
+        match self.index.lock().unwrap().get_mut(id) {
+            Some(meta) if meta.last_used() + ACCESS_PRECISION <= now => meta.accessed_at = now,
+            _ => return Ok(()),
+        }
+        self.update_meta(id, |meta| meta.accessed_at = now)
+    }
+
+    // changes the stored metadata, read again so the changes of other instances aren't overwritten
+    fn update_meta(&self, id: &str, update: impl FnOnce(&mut ImageMeta)) -> Result<(), FilterError> {
+        let mut meta = match self.read_meta(id)? {
+            Some(meta) => meta,
+            // deleted by another instance
+            None => {
+                self.index.lock().unwrap().remove(id);
+                return Ok(());
+            },
+        };
+
+        update(&mut meta);
+        self.save_meta(id, &meta)?;
+        self.index.lock().unwrap().insert(id.to_owned(), meta);
+        Ok(())
+    }
+
+    // adds the images stored by other instances and forgets the ones they deleted. returns every key
+    fn refresh(&self) -> Result<Vec<String>, FilterError> {
+        let keys = self.storage.list().map_err(storage_error)?;
+        let ids = keys.iter()
+            .filter_map(|key| key.strip_prefix(META_PREFIX)?.strip_suffix(".json"))
+            .collect::<HashSet<_>>();
+
+        let unseen = {
+            let mut index = self.index.lock().unwrap();
+            index.retain(|id, _| ids.contains(id.as_str()));
+            ids.iter().filter(|id| !index.contains_key(**id)).map(|id| id.to_string()).collect::<Vec<_>>()
+        };
+
+        let mut metas = Vec::new();
+        for id in unseen {
+            if let Some(meta) = self.read_meta(&id)? {
+                metas.push((id, meta));
+            }
+        }
+        self.index.lock().unwrap().extend(metas);
+
+        Ok(keys)
+    }
+
+    fn remove(&self, id: &str) -> Result<(), FilterError> {
+        let delete = |key: &str| self.storage.delete(key).map_err(|e|
+            FilterError::Other(format!("Failed to delete {}: {}", id, e))
+        );
+
+        delete(&meta_key(id))?;
+        self.index.lock().unwrap().remove(id);
+        delete(id)
+    }
+
+    fn read_meta(&self, id: &str) -> Result<Option<ImageMeta>, FilterError> {
+        match self.storage.read(&meta_key(id)).map_err(storage_error)? {
+            Some(meta) => serde_json::from_slice(&meta).map(Some).map_err(|e|
+                FilterError::Other(format!("Failed to read the metadata of {}: {}", id, e))
+            ),
+            None => Ok(None),
+        }
+    }
+
+    fn save_meta(&self, id: &str, meta: &ImageMeta) -> Result<(), FilterError> {
+        let json = serde_json::to_vec(meta).map_err(|e|
+            FilterError::Other(format!("Failed to write the metadata of {}: {}", id, e))
+        )?;
+
+        self.storage.write(&meta_key(id), &json).map_err(|e|
+            FilterError::Other(format!("Failed to write the metadata of {}: {}", id, e))
+        )
+    }
+}
+
+fn meta_key(id: &str) -> String {
+    format!("{}{}.json", META_PREFIX, id)
+}
+
+fn storage_error(e: io::Error) -> FilterError {
+    FilterError::Other(format!("Failed to access the image storage: {}", e))
+}
+
+fn lineage_tree(index: &HashMap<String, ImageMeta>, id: &str, meta: &ImageMeta) -> Value {
+    let sources = meta.derived_from.iter()
+        .map(|source| match index.get(source) {
+            Some(meta) => lineage_tree(index, source, meta),
+            None => json!({ "id": source, "deleted": true }),
+        })
+        .collect::<Vec<_>>();
+
+    let (operation, parameters, filter_version) = match &meta.lineage {
+        Some(lineage) => (Some(&lineage.operation), Some(&lineage.parameters), Some(&lineage.filter_version)),
+        None => (None, None, None),
+    };
+
+    json!({
+        "id": id,
+        "operation": operation,
+        "parameters": parameters,
+        "filter_version": filter_version,
+        "created_at": meta.created_at,
+        "sources": sources,
+    })
+}
+
+// seconds since the unix epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+// names coming from clients, either an upload or an id, must be a single plain file name
+pub fn check_name(field: &str, name: &str) -> Result<(), FilterError> {
+    let unsafe_name = name.is_empty()
+        || name.starts_with('.')
+        || name.chars().any(|c| matches!(c, '/' | '\\') || c.is_control());
+
+    if unsafe_name {
+        return Err(FilterError::invalid_field(field, format!("\"{}\" isn't a valid file name.", name.escape_default())));
+    }
+
+    Ok(())
+}
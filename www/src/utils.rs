@@ -1,125 +1,265 @@
 extern crate rocket_multipart_form_data;
 
-use filter::Algorithms;
+use filter::{Algorithms, BlendMode, Compression, EncodeOptions, Format};
+use crate::config::Config;
+use crate::file::FilterError;
+use crate::presets::{Operation, PresetStore, Step};
+use crate::request::{OutputQuery, Rect, Upload};
 
-use std::fs::{
-    self, File,
-};
-use std::path::{
-    Path, PathBuf,
-};
 use rocket::{
-    data::Data, http::ContentType,
-};
-use rand::{
-    Rng, distributions::Alphanumeric,
+    data::{ByteUnit, Data}, http::{Accept, ContentType}, serde::json::{json, serde_json::{self, Map}, Value},
 };
 use rocket_multipart_form_data::{
-    MultipartFormDataOptions, MultipartFormData, MultipartFormDataField,
+    MultipartFormDataOptions, MultipartFormData, MultipartFormDataError, MultipartFormDataField, RawField, Repetition,
 };
 
-pub async fn get_multipart_form_data(content_type: &ContentType, data: Data<'_>, fields: Vec<AllowedField<'_>>) -> MultipartFormData {
+pub async fn get_multipart_form_data(content_type: Option<&ContentType>, data: Data<'_>, fields: Vec<AllowedField<'_>>, config: &Config) -> Result<MultipartFormData, FilterError> {
+    let content_type = match content_type {
+        Some(content_type) if content_type.is_form_data() => content_type,
+        _ => return Err(FilterError::ContentType(String::from("Expected a \"multipart/form-data\" body."))),
+    };
+
     let mut options = MultipartFormDataOptions::new();
 
     for field in fields {
-        let multipart = field.into();
+        let multipart = field.into_field(config);
         options.allowed_fields.push(multipart);
     }
 
-    MultipartFormData::parse(content_type, data, options).await.unwrap()
+    MultipartFormData::parse(content_type, data, options).await.map_err(|e| match e {
+        MultipartFormDataError::DataTooLargeError(field) => FilterError::TooLarge {
+            limit: match &*field {
+                "archive" => archive_limit(config),
+                _ => config.max_upload_size,
+            },
+            field: field.to_string(),
+        },
+        MultipartFormDataError::IOError(e) => FilterError::Other(e.to_string()),
+        e => FilterError::MalformedBody(e.to_string()),
+    })
 }
 
-pub fn save_image(multipart_form_data: &mut MultipartFormData, field: &str) -> Result<PathBuf, String> {
-    if let Some(file_fields) = multipart_form_data.files.remove(field) {
-        let file_field = match file_fields.into_iter().next() {
-            Some(field) => field,
-            None => return Err(format!("missing element under \"{}\" field", field)),
-        };
+pub fn get_raw(multipart_form_data: &mut MultipartFormData, field: &str) -> Result<RawField, FilterError> {
+    if let Some(raw_fields) = multipart_form_data.raw.remove(field) {
+        match raw_fields.into_iter().next() {
+            Some(raw_field) => Ok(raw_field),
+            None => Err(FilterError::MissingField(String::from(field))),
+        }
+    } else {
+        Err(FilterError::MissingField(String::from(field)))
+    }
+}
 
-        use rocket_multipart_form_data::FileField;
-        let FileField { file_name, path, .. } = file_field;
+pub fn get_algo(multipart_form_data: &mut MultipartFormData, config: &Config) -> Result<(Algorithms, String), FilterError> {
+    let algorithm = get_text(multipart_form_data, "algorithm")?
+        .ok_or_else(|| FilterError::MissingField(String::from("algorithm")))?;
 
-        let file_name = match file_name {
-            Some(name) => name,
-            None => {
-                let mut rng = rand::thread_rng();
-                (0..30).map(|_| rng.sample(Alphanumeric) as char).collect()
-            }
-        };
+    let algo = Algorithms::try_from(algorithm.as_str())?;
+    config.allow(&algo)?;
 
-        let save_path = Path::new("static/images/").join(file_name);
+    let radius = match get_text(multipart_form_data, "radius")? {
+        Some(radius) if algo.need_radius() => Some(radius.parse::<u32>().map_err(|e| FilterError::invalid_field("radius", e))?),
+        _ => None,
+    };
 
-        if let Err(error) = File::create(&save_path) {
-            return Err(format!("An Error occured while creating file: {}", error));
-        }
+    let factor = match get_text(multipart_form_data, "factor")? {
+        Some(factor) if algo.need_factor() => Some(factor.parse::<i32>().map_err(|e| FilterError::invalid_field("factor", e))?),
+        _ => None,
+    };
 
-        match fs::copy(path, &save_path) {
-            Err(e) => Err(format!("An Error occured while saving file: {}", e)),
-            Ok(_) => Ok(save_path),
-        }
-    } else {
-        Err(format!("Missing \"{}\" field in form.", field))
+    let algo = with_parameters(algo, radius, factor)?;
+
+    // Algorithm enum + original name
+    // ex: (Algorithm::Blur(2), "blur")
+    Ok((algo, algorithm))
+}
+
+// either a `preset`, `steps` as a json array like the ones of a preset,
+// or an `algorithm` with its `radius` and `factor`
+pub fn get_operation(multipart_form_data: &mut MultipartFormData, config: &Config, presets: &PresetStore) -> Result<Operation, FilterError> {
+    let preset = get_text(multipart_form_data, "preset")?;
+    let steps = match get_text(multipart_form_data, "steps")? {
+        Some(steps) => Some(serde_json::from_str::<Vec<Step>>(&steps).map_err(|e| FilterError::invalid_field("steps", e))?),
+        None => None,
+    };
+
+    let given = match (&preset, &steps) {
+        (Some(_), Some(_)) => return Err(FilterError::invalid_field("steps", "can't be used with \"preset\"")),
+        (Some(_), None) => "preset",
+        (None, Some(_)) => "steps",
+        (None, None) => return get_algo(multipart_form_data, config).map(|(algo, _)| Operation::single(algo)),
+    };
+
+    if let Some(field) = ["algorithm", "radius", "factor"].iter().find(|field| multipart_form_data.texts.contains_key(**field)) {
+        return Err(FilterError::invalid_field(field, format!("can't be used with \"{}\"", given)));
+    }
+
+    match preset {
+        Some(preset) => presets.operation(&preset, config),
+        None => Operation::inline(steps.as_deref().unwrap_or_default(), config),
     }
 }
 
-pub fn get_algo(multipart_form_data: &mut MultipartFormData) -> Result<(Algorithms, String), String> {
-    let algorithm = match multipart_form_data.texts.remove("algorithm") {
-        None => Err(String::from("Missing \"algorithm\" field.")),
-        Some(algo) => {
-            if let Some(field) = algo.into_iter().next() {
-                Ok(field.text)
+// shared by the multipart and the json bodies, parameters the algorithm doesn't need are ignored
+pub fn with_parameters(mut algo: Algorithms, radius: Option<u32>, factor: Option<i32>) -> Result<Algorithms, FilterError> {
+    if let (true, Some(radius)) = (algo.need_radius(), radius) {
+        algo.set_radius(radius);
+    }
+
+    if let (true, Some(factor)) = (algo.need_factor(), factor) {
+        algo.set_factor(factor);
+    }
+
+    algo.validate()?;
+    Ok(algo)
+}
+
+pub fn get_roi(multipart_form_data: &mut MultipartFormData) -> Result<(Option<Rect>, Option<Upload>), FilterError> {
+    let rect = match multipart_form_data.texts.remove("roi") {
+        None => None,
+        Some(roi) => {
+            if let Some(field) = roi.into_iter().next() {
+                let values = field.text.split(',')
+                    .map(|value| value.trim().parse::<u32>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| FilterError::invalid_field("roi", e))?;
+
+                match values[..] {
+                    [x, y, width, height] => Some(Rect { x, y, width, height }),
+                    _ => return Err(FilterError::invalid_field("roi", "must be formatted as \"x,y,width,height\"")),
+                }
             } else {
-                Err(String::from("Missing value in \"algorithm\" field."))
+                return Err(FilterError::MissingField(String::from("roi")));
             }
         }
-    }?;
-
-    let mut algo = Algorithms::try_from(algorithm.as_str())?;
-
-    if algo.need_radius() {
-        let radius = match multipart_form_data.texts.remove("radius") {
-            None => Err(format!("{}: missing \"radius\" field", algo)),
-            Some(rad) => {
-                if let Some(field) = rad.into_iter().next() {
-                    field.text.parse::<u32>().map_err(|e| format!("{}: \"radius\" -> {}", algo, e))
-                } else {
-                    Err(format!("{}: missing value in \"radius\" field", algo))
-                }
-            }
-        }?;
+    };
 
-        algo.set_radius(radius);
+    let mask = if multipart_form_data.raw.contains_key("mask") {
+        Some(Upload::from(get_raw(multipart_form_data, "mask")?))
+    } else {
+        None
+    };
+
+    check_roi(rect, mask)
+}
+
+pub fn check_roi(rect: Option<Rect>, mask: Option<Upload>) -> Result<(Option<Rect>, Option<Upload>), FilterError> {
+    match (rect, mask) {
+        (Some(_), Some(_)) => Err(FilterError::invalid_field("mask", "can't be used together with \"roi\"")),
+        roi => Ok(roi),
     }
+}
 
-    if algo.need_factor() {
-        let factor = match multipart_form_data.texts.remove("factor") {
-            None => Err(format!("{}: missing \"factor\" field", algo)),
-            Some(fac) => {
-                if let Some(field) = fac.into_iter().next() {
-                    field.text.parse::<i32>().map_err(|e| format!("{}: \"factor\" -> {}", algo, e))
-                } else {
-                    Err(format!("{}: missing value in \"factor\" field", algo))
-                }
-            }
-        }?;
+pub fn get_blend(multipart_form_data: &mut MultipartFormData) -> Result<(BlendMode, f32), FilterError> {
+    let mode = match get_text(multipart_form_data, "mode")? {
+        Some(mode) => BlendMode::try_from(mode.as_str())?,
+        None => BlendMode::Normal,
+    };
 
-        algo.set_factor(factor);
+    let opacity = match get_text(multipart_form_data, "opacity")? {
+        Some(opacity) => opacity.parse::<f32>().map_err(|e| FilterError::invalid_field("opacity", e))?,
+        None => 1.,
+    };
+
+    Ok((mode, opacity))
+}
+
+pub fn get_output(multipart_form_data: &mut MultipartFormData, accept: Option<&Accept>, fallback: Format) -> Result<(Format, EncodeOptions), FilterError> {
+    let output = get_output_query(multipart_form_data)?;
+    parse_output(output.format.as_deref(), output.quality, output.compression.as_deref(), accept, fallback)
+}
+
+// the output fields, left unparsed when the fallback format isn't known yet
+pub fn get_output_query(multipart_form_data: &mut MultipartFormData) -> Result<OutputQuery, FilterError> {
+    let format = get_text(multipart_form_data, "format")?;
+    let quality = match get_text(multipart_form_data, "quality")? {
+        Some(quality) => Some(quality.parse::<u8>().map_err(|e| FilterError::invalid_field("quality", e))?),
+        None => None,
+    };
+    let compression = get_text(multipart_form_data, "compression")?;
+
+    Ok(OutputQuery { format, quality, compression })
+}
+
+pub fn parse_output(format: Option<&str>, quality: Option<u8>, compression: Option<&str>, accept: Option<&Accept>, fallback: Format) -> Result<(Format, EncodeOptions), FilterError> {
+    let format = match format {
+        Some(format) => Format::try_from(format)?,
+        None => accept.and_then(negotiate_format).unwrap_or(fallback),
+    };
+
+    let mut options = EncodeOptions::default();
+
+    if let Some(quality) = quality {
+        options.quality = quality;
     }
 
-    // Algorithm enum + original name
-    // ex: (Algorithm::Blur(2), "blur")
-    Ok((algo, algorithm))
+    if let Some(compression) = compression {
+        options.compression = Compression::try_from(compression)?;
+    }
+
+    Ok((format, options))
+}
+
+// the most preferred format of the `Accept` header we can encode
+fn negotiate_format(accept: &Accept) -> Option<Format> {
+    let mut media_types = accept.iter().collect::<Vec<_>>();
+    media_types.sort_by(|lhs, rhs| rhs.weight_or(1.).total_cmp(&lhs.weight_or(1.)));
+
+    media_types.into_iter()
+        .find_map(|media| Format::from_mime(&format!("{}/{}", media.top(), media.sub())))
+}
+
+pub fn get_persist(multipart_form_data: &mut MultipartFormData) -> Result<bool, FilterError> {
+    match get_text(multipart_form_data, "persist")? {
+        Some(persist) => persist.parse::<bool>().map_err(|e| FilterError::invalid_field("persist", e)),
+        None => Ok(false),
+    }
+}
+
+fn get_text(multipart_form_data: &mut MultipartFormData, field: &str) -> Result<Option<String>, FilterError> {
+    match multipart_form_data.texts.remove(field) {
+        None => Ok(None),
+        Some(text) => {
+            if let Some(text) = text.into_iter().next() {
+                Ok(Some(text.text))
+            } else {
+                Err(FilterError::MissingField(String::from(field)))
+            }
+        }
+    }
+}
+
+// every parameter of `algo` with its value, ex: {"radius": 3, "factor": 5}
+pub fn arguments(algo: &Algorithms) -> Value {
+    algo.arguments().into_iter()
+        .map(|(name, value)| (String::from(name), json!(value)))
+        .collect::<Map<_, _>>()
+        .into()
+}
+
+// a whole batch of images in a single archive
+pub fn archive_limit(config: &Config) -> ByteUnit {
+    config.max_upload_size * config.max_batch_size as u64
 }
 
 pub enum AllowedField<'a>{
-    File(&'a str),
+    Raw(&'a str),
+    // the same file field, at most that many times
+    Raws(&'a str, u32),
+    Archive(&'a str),
     Text(&'a str),
 }
 
-impl<'a> From<AllowedField<'a>> for MultipartFormDataField<'a> {
-    fn from(field: AllowedField<'a>) -> Self {
-        match field {
-            AllowedField::File(field_name) => MultipartFormDataField::file(field_name),
+impl<'a> AllowedField<'a> {
+    fn into_field(self, config: &Config) -> MultipartFormDataField<'a> {
+        let size_limit = config.max_upload_size.as_u64();
+
+        match self {
+            AllowedField::Raw(field_name) => MultipartFormDataField::raw(field_name).size_limit(size_limit),
+            AllowedField::Raws(field_name, count) => MultipartFormDataField::raw(field_name)
+                .size_limit(size_limit)
+                .repetition(Repetition::fixed(count)),
+            AllowedField::Archive(field_name) => MultipartFormDataField::raw(field_name).size_limit(archive_limit(config).as_u64()),
             AllowedField::Text(field_name) => MultipartFormDataField::text(field_name),
         }
     }